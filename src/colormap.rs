@@ -0,0 +1,295 @@
+use anyhow::{bail, Context};
+use std::str::FromStr;
+
+/// Number of entries in the lookup table uploaded to the GPU
+pub const LUT_SIZE: usize = 256;
+
+/// Suffix reversing a colormap, as in matplotlib
+const REVERSED_SUFFIX: &str = "_r";
+
+/// A colormap given by equally spaced sRGB colors, interpolated linearly.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Colormap {
+    pub name: String,
+    colors: Vec<[u8; 4]>,
+}
+
+// Anchor points of the named colormaps, sampled at equal distances from the
+// matplotlib and ColorBrewer definitions.
+
+const VIRIDIS: &[[u8; 3]] = &[
+    [68, 1, 84],
+    [71, 43, 122],
+    [59, 81, 138],
+    [44, 113, 142],
+    [32, 143, 140],
+    [39, 173, 128],
+    [91, 200, 98],
+    [170, 219, 50],
+    [253, 231, 36],
+];
+
+const MAGMA: &[[u8; 3]] = &[
+    [0, 0, 3],
+    [29, 17, 71],
+    [80, 18, 123],
+    [130, 38, 129],
+    [182, 54, 121],
+    [230, 81, 99],
+    [251, 135, 97],
+    [254, 195, 135],
+    [251, 252, 191],
+];
+
+const CIVIDIS: &[[u8; 3]] = &[
+    [0, 32, 76],
+    [1, 53, 110],
+    [64, 76, 107],
+    [95, 99, 110],
+    [123, 123, 120],
+    [155, 147, 119],
+    [188, 174, 110],
+    [223, 203, 93],
+    [255, 233, 69],
+];
+
+const RD_BU: &[[u8; 3]] = &[
+    [103, 0, 31],
+    [178, 24, 43],
+    [214, 96, 77],
+    [244, 165, 130],
+    [253, 219, 199],
+    [247, 247, 247],
+    [209, 229, 240],
+    [146, 197, 222],
+    [67, 147, 195],
+    [33, 102, 172],
+    [5, 48, 97],
+];
+
+const SEISMIC: &[[u8; 3]] = &[
+    [0, 0, 77],
+    [0, 0, 255],
+    [255, 255, 255],
+    [255, 0, 0],
+    [128, 0, 0],
+];
+
+const COOLWARM: &[[u8; 3]] = &[
+    [59, 76, 192],
+    [98, 130, 234],
+    [141, 176, 254],
+    [184, 208, 249],
+    [221, 221, 221],
+    [245, 196, 173],
+    [244, 154, 123],
+    [222, 96, 77],
+    [180, 4, 38],
+];
+
+const TWILIGHT: &[[u8; 3]] = &[
+    [226, 217, 226],
+    [166, 189, 206],
+    [106, 143, 193],
+    [93, 83, 164],
+    [47, 20, 54],
+    [125, 37, 84],
+    [177, 83, 77],
+    [209, 158, 136],
+    [226, 217, 226],
+];
+
+const GRAYSCALE: &[[u8; 3]] = &[[0, 0, 0], [255, 255, 255]];
+
+impl Colormap {
+    /// Names of the built in colormaps, each can be reversed by appending `_r`.
+    pub const NAMES: &'static [&'static str] = &[
+        "viridis",
+        "magma",
+        "cividis",
+        "RdBu",
+        "seismic",
+        "coolwarm",
+        "twilight",
+        "grayscale",
+    ];
+
+    /// Colorblind safe default for signed fields, negative values are blue
+    pub const DEFAULT_DIVERGING: &'static str = "RdBu_r";
+
+    /// Look up a built in colormap by name.
+    pub fn named(name: &str) -> Option<Self> {
+        if let Some(base) = name.strip_suffix(REVERSED_SUFFIX) {
+            return Self::named(base).map(Self::reversed);
+        }
+
+        let anchors = match name {
+            "viridis" => VIRIDIS,
+            "magma" => MAGMA,
+            "cividis" => CIVIDIS,
+            "RdBu" => RD_BU,
+            "seismic" => SEISMIC,
+            "coolwarm" => COOLWARM,
+            "twilight" => TWILIGHT,
+            "grayscale" => GRAYSCALE,
+            _ => return None,
+        };
+
+        Some(Self {
+            name: name.to_string(),
+            colors: anchors.iter().map(|&[r, g, b]| [r, g, b, 255]).collect(),
+        })
+    }
+
+    /// Load a colormap from a CSV or LUT file.
+    ///
+    /// Every line holds one color as three or four values separated by commas
+    /// or whitespace, either as floats in `[0, 1]` or as integers in
+    /// `[0, 255]`. Empty lines and lines starting with `#` are ignored.
+    pub fn from_file(path: &str) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read colormap {}", path))?;
+        Self::parse(path, &text).with_context(|| format!("Failed to parse colormap {}", path))
+    }
+
+    fn parse(name: &str, text: &str) -> anyhow::Result<Self> {
+        let mut rows = vec![];
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let values = line
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|v| !v.is_empty())
+                .map(f64::from_str)
+                .collect::<Result<Vec<_>, _>>()
+                .with_context(|| format!("Invalid number in line {}", n + 1))?;
+            if !(3..=4).contains(&values.len()) {
+                bail!("Expected 3 or 4 values in line {}", n + 1);
+            }
+            rows.push(values);
+        }
+        if rows.len() < 2 {
+            bail!("A colormap needs at least two colors");
+        }
+
+        // integers in [0, 255] if any value exceeds 1
+        let scale = if rows.iter().flatten().any(|&v| v > 1.0) {
+            1.0
+        } else {
+            255.0
+        };
+        let colors = rows
+            .iter()
+            .map(|row| {
+                let channel = |i: usize| (row[i] * scale).clamp(0.0, 255.0);
+                [
+                    channel(0).round() as u8,
+                    channel(1).round() as u8,
+                    channel(2).round() as u8,
+                    if row.len() == 4 { channel(3) } else { 255.0 }.round() as u8,
+                ]
+            })
+            .collect();
+
+        Ok(Self {
+            name: name.to_string(),
+            colors,
+        })
+    }
+
+    pub fn reversed(mut self) -> Self {
+        self.colors.reverse();
+        self.name = match self.name.strip_suffix(REVERSED_SUFFIX) {
+            Some(base) => base.to_string(),
+            None => self.name + REVERSED_SUFFIX,
+        };
+        self
+    }
+
+    /// The built in colormap after this one, reversed if this one is. Maps
+    /// loaded from files continue with the first built in one.
+    pub fn next(&self) -> Self {
+        let (base, reversed) = match self.name.strip_suffix(REVERSED_SUFFIX) {
+            Some(base) => (base, true),
+            None => (self.name.as_str(), false),
+        };
+        let current = Self::NAMES.iter().position(|&name| name == base);
+        let next = current.map_or(0, |n| (n + 1) % Self::NAMES.len());
+        let next = Self::named(Self::NAMES[next]).unwrap();
+        if reversed {
+            next.reversed()
+        } else {
+            next
+        }
+    }
+
+    /// Sample the colormap into [`LUT_SIZE`] sRGB colors.
+    pub fn lut(&self) -> Vec<[u8; 4]> {
+        let segments = (self.colors.len() - 1) as f64;
+        (0..LUT_SIZE)
+            .map(|i| {
+                let pos = i as f64 / (LUT_SIZE - 1) as f64 * segments;
+                let n = (pos.floor() as usize).min(self.colors.len() - 2);
+                let f = pos - n as f64;
+                let (a, b) = (self.colors[n], self.colors[n + 1]);
+                std::array::from_fn(|c| {
+                    (a[c] as f64 + f * (b[c] as f64 - a[c] as f64)).round() as u8
+                })
+            })
+            .collect()
+    }
+}
+
+impl FromStr for Colormap {
+    type Err = anyhow::Error;
+
+    /// Either the name of a built in colormap or the path to a colormap file.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match Self::named(s) {
+            Some(colormap) => Ok(colormap),
+            None => Self::from_file(s),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_named() {
+        for name in Colormap::NAMES {
+            let colormap = Colormap::named(name).unwrap();
+            assert_eq!(colormap.lut().len(), LUT_SIZE);
+        }
+        assert!(Colormap::named("jet").is_none());
+    }
+
+    #[test]
+    fn test_reversed() {
+        let colormap = Colormap::named("viridis_r").unwrap();
+        assert_eq!(colormap.name, "viridis_r");
+        assert_eq!(colormap.lut()[0], [253, 231, 36, 255]);
+        assert_eq!(colormap.lut()[LUT_SIZE - 1], [68, 1, 84, 255]);
+        assert_eq!(colormap.next().name, "magma_r");
+        assert_eq!(colormap.reversed().name, "viridis");
+        assert_eq!(Colormap::named("grayscale").unwrap().next().name, "viridis");
+    }
+
+    #[test]
+    fn test_parse() {
+        let floats =
+            Colormap::parse("floats", "# comment\n0.0, 0.0, 0.0\n\n1.0 0.5 0.0 0.5\n").unwrap();
+        assert_eq!(floats.colors, vec![[0, 0, 0, 255], [255, 128, 0, 128]]);
+
+        let bytes = Colormap::parse("bytes", "0 0 0\n255 255 255\n").unwrap();
+        assert_eq!(bytes.lut()[0], [0, 0, 0, 255]);
+        assert_eq!(bytes.lut()[128], [128, 128, 128, 255]);
+
+        assert!(Colormap::parse("short", "0 0 0\n").is_err());
+        assert!(Colormap::parse("columns", "0 0\n1 1\n").is_err());
+        assert!(Colormap::parse("nan", "0 0 x\n1 1 1\n").is_err());
+    }
+}
@@ -0,0 +1,328 @@
+use std::io::{self, Write};
+use std::str::FromStr;
+
+/// Energy flux averaged over the blocks of a coarse grid, for drawing arrows.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FluxField {
+    /// Blocks along each side of the domain
+    pub grid: u32,
+    /// Mean flux of each block in row major order
+    pub vectors: Vec<(f64, f64)>,
+}
+
+impl FluxField {
+    /// Blocks along each side drawn as arrows
+    pub const GRID: u32 = 32;
+
+    /// Average the `flux` of a grid of `discretization` cells along each side
+    /// over the blocks of a grid of `grid` blocks.
+    pub fn new(flux: &[(f64, f64)], discretization: u32, grid: u32) -> Self {
+        let (n, grid) = (discretization as usize, grid.min(discretization) as usize);
+        let mut sums = vec![((0.0, 0.0), 0usize); grid * grid];
+        for (i, (sx, sy)) in flux.iter().enumerate() {
+            let block = (i / n * grid / n) * grid + i % n * grid / n;
+            let ((x, y), count) = &mut sums[block];
+            *x += sx;
+            *y += sy;
+            *count += 1;
+        }
+        Self {
+            grid: grid as u32,
+            vectors: sums
+                .into_iter()
+                .map(|((x, y), count)| (x / count as f64, y / count as f64))
+                .collect(),
+        }
+    }
+
+    /// Magnitude drawn at the full length of an arrow, a high percentile of
+    /// the blocks so the singular flux at the source does not shrink all
+    /// other arrows.
+    pub fn reference_magnitude(&self) -> f64 {
+        const PERCENTILE: f64 = 0.9;
+        let mut magnitudes: Vec<f64> = self.vectors.iter().map(|(x, y)| x.hypot(*y)).collect();
+        magnitudes.sort_by(f64::total_cmp);
+        let rank = ((magnitudes.len() - 1) as f64 * PERCENTILE).round() as usize;
+        magnitudes[rank]
+    }
+}
+
+/// Point in the domain in m
+type Point = (f64, f64);
+
+/// Where a [`Monitor`] measures the flux, in m.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shape {
+    /// Line from the first to the second point, its normal points to the
+    /// left of the direction on screen, `+x` for a line drawn downwards
+    Line((f64, f64), (f64, f64)),
+    /// Rectangle between two corners, its normals point outwards
+    Box((f64, f64), (f64, f64)),
+}
+
+impl Shape {
+    /// Straight sides as start, end and unit normal.
+    pub fn sides(&self) -> Vec<(Point, Point, Point)> {
+        let side = |start: (f64, f64), end: (f64, f64)| {
+            let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+            let length = dx.hypot(dy);
+            (start, end, (dy / length, -dx / length))
+        };
+        match *self {
+            Shape::Line(start, end) => vec![side(start, end)],
+            Shape::Box(a, b) => {
+                let (left, right) = (a.0.min(b.0), a.0.max(b.0));
+                let (top, bottom) = (a.1.min(b.1), a.1.max(b.1));
+                // clockwise on screen, so the normals point outwards
+                vec![
+                    side((left, bottom), (left, top)),
+                    side((left, top), (right, top)),
+                    side((right, top), (right, bottom)),
+                    side((right, bottom), (left, bottom)),
+                ]
+            }
+        }
+    }
+}
+
+/// Integrates the energy flux through a line or box over time.
+///
+/// Flux along the normal counts as forward, flux against it as backward,
+/// separately for every piece of the shape. Placed between a source and an
+/// interface, the backward energy is the reflected one as long as incident
+/// and reflected waves do not overlap at the monitor.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Monitor {
+    pub shape: Shape,
+    /// Cell, unit normal and length of each piece of the shape
+    pieces: Vec<(usize, (f64, f64), f64)>,
+    /// Energy carried along the normal since the last reset
+    forward: f64,
+    /// Energy carried against the normal since the last reset
+    backward: f64,
+    /// Time integrated over
+    duration: f64,
+}
+
+/// Mean power through a [`Monitor`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Report {
+    pub shape: Shape,
+    /// Power along the normal
+    pub forward: f64,
+    /// Power against the normal
+    pub backward: f64,
+}
+
+impl Report {
+    /// Backward over forward power, the reflection coefficient of what lies
+    /// behind the monitor. `None` until power arrives along the normal.
+    pub fn reflection(&self) -> Option<f64> {
+        ratio(self.backward, self.forward)
+    }
+
+    /// Forward power relative to that of `incident`, the transmission
+    /// coefficient between the two monitors. `None` until power arrives at
+    /// `incident`.
+    pub fn transmission(&self, incident: &Report) -> Option<f64> {
+        ratio(self.forward, incident.forward)
+    }
+}
+
+fn ratio(numerator: f64, denominator: f64) -> Option<f64> {
+    (denominator > 0.0).then(|| numerator / denominator)
+}
+
+impl Monitor {
+    pub fn new(shape: Shape) -> Self {
+        Self {
+            shape,
+            pieces: vec![],
+            forward: 0.0,
+            backward: 0.0,
+            duration: 0.0,
+        }
+    }
+
+    /// Rectangle between two corners given as `x1,y1,x2,y2`.
+    pub fn parse_box(s: &str) -> anyhow::Result<Self> {
+        let (a, b) = parse_points(s)?;
+        if a.0 == b.0 || a.1 == b.1 {
+            anyhow::bail!("Expected a box of nonzero width and height, got `{}`", s);
+        }
+        Ok(Self::new(Shape::Box(a, b)))
+    }
+
+    /// Split the shape into pieces of about a cell on a grid of
+    /// `discretization` cells along each side of a domain of `size` in m.
+    pub fn discretize(&mut self, size: (f64, f64), discretization: u32) {
+        let n = discretization as usize;
+        let cell = (size.0 / n as f64).min(size.1 / n as f64);
+        let index =
+            |x: f64, size: f64| ((x / size * n as f64).floor().max(0.0) as usize).min(n - 1);
+
+        self.pieces.clear();
+        for (start, end, normal) in self.shape.sides() {
+            let length = (end.0 - start.0).hypot(end.1 - start.1);
+            let count = (length / cell).ceil().max(1.0) as usize;
+            for i in 0..count {
+                // midpoint of the piece
+                let f = (i as f64 + 0.5) / count as f64;
+                let x = start.0 + f * (end.0 - start.0);
+                let y = start.1 + f * (end.1 - start.1);
+                let cell = index(y, size.1) * n + index(x, size.0);
+                self.pieces.push((cell, normal, length / count as f64));
+            }
+        }
+    }
+
+    /// Integrate the flux of each cell given by `flux` over a step of `dt`.
+    pub fn record(&mut self, flux: impl Fn(usize) -> (f64, f64), dt: f64) {
+        let dt = dt.abs();
+        for &(cell, normal, length) in &self.pieces {
+            let (sx, sy) = flux(cell);
+            let energy = (sx * normal.0 + sy * normal.1) * length * dt;
+            if energy > 0.0 {
+                self.forward += energy;
+            } else {
+                self.backward -= energy;
+            }
+        }
+        self.duration += dt;
+    }
+
+    pub fn reset(&mut self) {
+        self.forward = 0.0;
+        self.backward = 0.0;
+        self.duration = 0.0;
+    }
+
+    pub fn report(&self) -> Report {
+        let duration = self.duration.max(f64::MIN_POSITIVE);
+        Report {
+            shape: self.shape,
+            forward: self.forward / duration,
+            backward: self.backward / duration,
+        }
+    }
+}
+
+impl FromStr for Monitor {
+    type Err = anyhow::Error;
+
+    /// Line between two points given as `x1,y1,x2,y2`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (a, b) = parse_points(s)?;
+        if a == b {
+            anyhow::bail!("Expected a line of nonzero length, got `{}`", s);
+        }
+        Ok(Self::new(Shape::Line(a, b)))
+    }
+}
+
+fn parse_points(s: &str) -> anyhow::Result<(Point, Point)> {
+    let coordinates = s
+        .split(',')
+        .map(|c| c.trim().parse())
+        .collect::<Result<Vec<f64>, _>>()?;
+    match coordinates[..] {
+        [x1, y1, x2, y2] => Ok(((x1, y1), (x2, y2))),
+        _ => anyhow::bail!("Expected two points `x1,y1,x2,y2`, got `{}`", s),
+    }
+}
+
+/// Write the mean powers through all monitors as CSV, with the transmission
+/// relative to the first monitor. Undefined ratios are left empty.
+pub fn write_csv(reports: &[Report], mut writer: impl Write) -> io::Result<()> {
+    let cell = |ratio: Option<f64>| ratio.map_or(String::new(), |r| format!("{:e}", r));
+    writeln!(writer, "monitor,forward,backward,reflection,transmission")?;
+    for (i, report) in reports.iter().enumerate() {
+        // the first monitor is the reference of the others
+        let transmission = (i > 0).then(|| report.transmission(&reports[0])).flatten();
+        writeln!(
+            writer,
+            "{},{:e},{:e},{},{}",
+            i + 1,
+            report.forward,
+            report.backward,
+            cell(report.reflection()),
+            cell(transmission)
+        )?;
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flux_field() {
+        // flux to the right in the left half, downwards in the right half
+        let flux: Vec<(f64, f64)> = (0..16)
+            .map(|i| if i % 4 < 2 { (1.0, 0.0) } else { (0.0, 2.0) })
+            .collect();
+        let field = FluxField::new(&flux, 4, 2);
+        assert_eq!(field.grid, 2);
+        assert_eq!(
+            field.vectors,
+            vec![(1.0, 0.0), (0.0, 2.0), (1.0, 0.0), (0.0, 2.0)]
+        );
+        assert_eq!(field.reference_magnitude(), 2.0);
+
+        // grids finer than the cells fall back to the cells
+        assert_eq!(FluxField::new(&flux, 4, 8).grid, 4);
+    }
+
+    #[test]
+    fn test_monitor() {
+        let mut line: Monitor = "2, 0, 2, 4".parse().unwrap();
+        assert_eq!(line.shape.sides()[0].2, (1.0, 0.0));
+        line.discretize((4.0, 4.0), 4);
+        assert_eq!(line.pieces.len(), 4);
+        assert_eq!(line.pieces[1], (6, (1.0, 0.0), 1.0));
+
+        // a unit flux to the right for 2 s, then half of it back for 1 s
+        line.record(|_| (1.0, 0.0), 2.0);
+        line.record(|_| (-0.5, 0.0), -1.0);
+        let report = line.report();
+        assert_eq!(report.forward, 8.0 / 3.0);
+        assert_eq!(report.backward, 2.0 / 3.0);
+        assert_eq!(report.reflection(), Some(0.25));
+
+        // flux out of a box through all four sides
+        let mut enclosure = Monitor::parse_box("1,1,3,3").unwrap();
+        enclosure.discretize((4.0, 4.0), 4);
+        enclosure.record(
+            |cell| {
+                if cell % 4 < 2 {
+                    (-1.0, 0.0)
+                } else {
+                    (1.0, 0.0)
+                }
+            },
+            1.0,
+        );
+        assert_eq!(enclosure.report().forward, 4.0);
+        assert_eq!(enclosure.report().backward, 0.0);
+        assert_eq!(enclosure.report().transmission(&report), Some(1.5));
+
+        // nothing arrived yet
+        let idle = Monitor::parse_box("1,1,3,3").unwrap().report();
+        assert_eq!(idle.reflection(), None);
+        assert_eq!(report.transmission(&idle), None);
+        let mut csv = vec![];
+        write_csv(&[idle, report], &mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "monitor,forward,backward,reflection,transmission\n\
+             1,0e0,0e0,,\n\
+             2,2.6666666666666665e0,6.666666666666666e-1,2.5e-1,\n"
+        );
+
+        assert!("1,2,3".parse::<Monitor>().is_err());
+        // shapes without a normal
+        assert!("1,2,1,2".parse::<Monitor>().is_err());
+        assert!(Monitor::parse_box("1,1,1,3").is_err());
+    }
+}
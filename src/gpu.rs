@@ -0,0 +1,141 @@
+/// Graphics API used to talk to the GPU.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Backend {
+    /// The native API of the platform
+    Primary,
+    Vulkan,
+    /// OpenGL or OpenGL ES
+    Gl,
+    Metal,
+    Dx12,
+    /// Any API, including secondary ones like OpenGL
+    #[default]
+    All,
+}
+
+impl From<Backend> for wgpu::Backends {
+    fn from(backend: Backend) -> Self {
+        match backend {
+            Backend::Primary => wgpu::Backends::PRIMARY,
+            Backend::Vulkan => wgpu::Backends::VULKAN,
+            Backend::Gl => wgpu::Backends::GL,
+            Backend::Metal => wgpu::Backends::METAL,
+            Backend::Dx12 => wgpu::Backends::DX12,
+            Backend::All => wgpu::Backends::all(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum PowerPreference {
+    /// Let the driver decide
+    #[default]
+    None,
+    /// Prefer an integrated GPU
+    Low,
+    /// Prefer a discrete GPU
+    High,
+}
+
+impl From<PowerPreference> for wgpu::PowerPreference {
+    fn from(power_preference: PowerPreference) -> Self {
+        match power_preference {
+            PowerPreference::None => wgpu::PowerPreference::None,
+            PowerPreference::Low => wgpu::PowerPreference::LowPower,
+            PowerPreference::High => wgpu::PowerPreference::HighPerformance,
+        }
+    }
+}
+
+/// How frames are handed to the display.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum PresentMode {
+    /// Wait for the vertical blank, supported everywhere
+    #[default]
+    Vsync,
+    /// Show frames immediately, may tear
+    NoVsync,
+    /// Replace the queued frame without tearing
+    Mailbox,
+}
+
+impl PresentMode {
+    /// The wgpu present mode, falling back to vsync if `supported` lacks it.
+    pub fn resolve(self, supported: &[wgpu::PresentMode]) -> wgpu::PresentMode {
+        let wanted = match self {
+            PresentMode::Vsync => wgpu::PresentMode::Fifo,
+            PresentMode::NoVsync => wgpu::PresentMode::Immediate,
+            PresentMode::Mailbox => wgpu::PresentMode::Mailbox,
+        };
+        if supported.contains(&wanted) {
+            wanted
+        } else {
+            log::warn!("Present mode {:?} is not supported, using vsync", wanted);
+            wgpu::PresentMode::Fifo
+        }
+    }
+}
+
+/// Choice of the GPU and how it presents frames.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Options {
+    pub backend: Backend,
+    pub power_preference: PowerPreference,
+    /// Use a software adapter
+    pub force_fallback_adapter: bool,
+    pub present_mode: PresentMode,
+    /// Frames the GPU may queue ahead of the display
+    pub frame_latency: u32,
+}
+
+impl Options {
+    pub fn instance(&self) -> wgpu::Instance {
+        wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: self.backend.into(),
+            ..Default::default()
+        })
+    }
+
+    /// Request an adapter matching the options, able to draw to `surface` if given.
+    pub async fn request_adapter(
+        &self,
+        instance: &wgpu::Instance,
+        surface: Option<&wgpu::Surface<'_>>,
+    ) -> Option<wgpu::Adapter> {
+        instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: self.power_preference.into(),
+                compatible_surface: surface,
+                force_fallback_adapter: self.force_fallback_adapter,
+            })
+            .await
+    }
+}
+
+/// Name, type, backend and driver of an adapter, for telling the user which
+/// one was chosen.
+pub fn describe_adapter(info: &wgpu::AdapterInfo) -> String {
+    format!(
+        "{} ({:?}, {:?} backend, driver {} {})",
+        info.name, info.device_type, info.backend, info.driver, info.driver_info
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_present_mode() {
+        let supported = [wgpu::PresentMode::Fifo, wgpu::PresentMode::Immediate];
+        assert_eq!(
+            PresentMode::NoVsync.resolve(&supported),
+            wgpu::PresentMode::Immediate
+        );
+        assert_eq!(
+            PresentMode::Mailbox.resolve(&supported),
+            wgpu::PresentMode::Fifo
+        );
+        assert_eq!(PresentMode::Vsync.resolve(&[]), wgpu::PresentMode::Fifo);
+    }
+}
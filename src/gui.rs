@@ -0,0 +1,266 @@
+use crate::{colormap, pacing, range, sim, vis};
+
+/// Values that can be edited live in the control panel.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Controls {
+    pub params: sim::Parameters,
+    /// Time step in seconds
+    pub dt: f64,
+    pub pacing: pacing::Settings,
+}
+
+/// Immediate mode control panel drawn on top of the simulation.
+pub struct Gui {
+    context: egui::Context,
+    state: egui_winit::State,
+    renderer: egui_wgpu::Renderer,
+    paint_jobs: Vec<egui::ClippedPrimitive>,
+    textures_delta: egui::TexturesDelta,
+    screen: egui_wgpu::ScreenDescriptor,
+    pub visible: bool,
+}
+
+impl Gui {
+    pub fn new(
+        window: &winit::window::Window,
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        let context = egui::Context::default();
+        let state = egui_winit::State::new(
+            context.clone(),
+            egui::ViewportId::ROOT,
+            window,
+            Some(window.scale_factor() as f32),
+            Some(device.limits().max_texture_dimension_2d as usize),
+        );
+        let renderer = egui_wgpu::Renderer::new(device, format, None, 1);
+
+        Self {
+            context,
+            state,
+            renderer,
+            paint_jobs: vec![],
+            textures_delta: egui::TexturesDelta::default(),
+            screen: egui_wgpu::ScreenDescriptor {
+                size_in_pixels: [0, 0],
+                pixels_per_point: 1.0,
+            },
+            visible: true,
+        }
+    }
+
+    /// Pass a window event to the panel, returns whether the panel consumed it.
+    pub fn handle_event(
+        &mut self,
+        window: &winit::window::Window,
+        event: &winit::event::WindowEvent,
+    ) -> bool {
+        if !self.visible {
+            return false;
+        }
+        self.state.on_window_event(window, event).consumed
+    }
+
+    /// Run the panel for this frame and upload everything needed to draw it.
+    #[allow(clippy::too_many_arguments)]
+    pub fn prepare(
+        &mut self,
+        window: &winit::window::Window,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        controls: &mut Controls,
+        settings: &mut vis::Settings,
+        status: &vis::Status,
+    ) {
+        let input = self.state.take_egui_input(window);
+        let output = self.context.run(input, |ctx| {
+            Self::panel(ctx, controls, settings, status);
+        });
+        self.state
+            .handle_platform_output(window, output.platform_output);
+
+        self.paint_jobs = self
+            .context
+            .tessellate(output.shapes, output.pixels_per_point);
+        self.screen = egui_wgpu::ScreenDescriptor {
+            size_in_pixels: [window.inner_size().width, window.inner_size().height],
+            pixels_per_point: output.pixels_per_point,
+        };
+
+        for (id, delta) in &output.textures_delta.set {
+            self.renderer.update_texture(device, queue, *id, delta);
+        }
+        self.renderer
+            .update_buffers(device, queue, encoder, &self.paint_jobs, &self.screen);
+        self.textures_delta = output.textures_delta;
+    }
+
+    pub fn render<'pass>(&'pass self, render_pass: &mut wgpu::RenderPass<'pass>) {
+        self.renderer
+            .render(render_pass, &self.paint_jobs, &self.screen);
+    }
+
+    /// Free textures egui no longer needs, after the frame was submitted.
+    pub fn cleanup(&mut self) {
+        for id in &self.textures_delta.free {
+            self.renderer.free_texture(id);
+        }
+        self.textures_delta.free.clear();
+    }
+
+    fn panel(
+        ctx: &egui::Context,
+        controls: &mut Controls,
+        settings: &mut vis::Settings,
+        status: &vis::Status,
+    ) {
+        egui::Window::new("Controls").show(ctx, |ui| {
+            ui.heading("Simulation");
+            ui.add(egui::Slider::new(&mut controls.params.c, 0.01..=10.0).text("c [m/s]"));
+            ui.add(
+                egui::Slider::new(&mut controls.dt, 1e-6..=1e-1)
+                    .logarithmic(true)
+                    .text("dt [s]"),
+            );
+            let courant = egui::RichText::new(format!("Courant number: {:.3}", status.courant));
+            if status.courant > 1.0 {
+                ui.label(courant.color(egui::Color32::RED))
+                    .on_hover_text("The simulation is unstable above 1");
+            } else {
+                ui.label(courant);
+            }
+            ui.add(
+                egui::Slider::new(&mut controls.params.damping, 0.0..=10.0).text("damping [1/s]"),
+            );
+
+            ui.heading("Playback");
+            egui::ComboBox::from_label("pacing")
+                .selected_text(controls.pacing.pacing.name())
+                .show_ui(ui, |ui| {
+                    for pacing in pacing::Pacing::ALL {
+                        ui.selectable_value(&mut controls.pacing.pacing, pacing, pacing.name());
+                    }
+                });
+            match controls.pacing.pacing {
+                pacing::Pacing::Steps => ui.add(
+                    egui::Slider::new(&mut controls.pacing.steps_per_frame, 1..=1000)
+                        .logarithmic(true)
+                        .text("steps / frame"),
+                ),
+                pacing::Pacing::Realtime => ui.add(
+                    egui::Slider::new(&mut controls.pacing.rate, 1e-4..=10.0)
+                        .logarithmic(true)
+                        .text("rate [s/s]"),
+                ),
+                pacing::Pacing::Adaptive => ui.add(
+                    egui::Slider::new(&mut controls.pacing.target_fps, 5.0..=144.0)
+                        .text("target fps"),
+                ),
+            };
+
+            ui.heading("Source");
+            ui.add(
+                egui::Slider::new(&mut controls.params.omega, 0.01..=100.0)
+                    .logarithmic(true)
+                    .text("ω [rad/s]"),
+            );
+            ui.add(egui::Slider::new(&mut controls.params.amplitude, 0.0..=10.0).text("amplitude"));
+
+            ui.heading("View");
+            egui::ComboBox::from_label("render mode")
+                .selected_text(settings.render_mode.name())
+                .show_ui(ui, |ui| {
+                    for render_mode in vis::RenderMode::ALL {
+                        ui.selectable_value(
+                            &mut settings.render_mode,
+                            render_mode,
+                            render_mode.name(),
+                        );
+                    }
+                });
+            ui.checkbox(&mut settings.show_medium, "show walls and media");
+            if settings.render_mode != vis::RenderMode::Flat {
+                ui.add(egui::Slider::new(&mut settings.height, 0.0..=1.0).text("height"));
+            }
+            if settings.render_mode == vis::RenderMode::RippleTank {
+                ui.add(
+                    egui::Slider::new(&mut settings.lamp_height, 0.1..=100.0)
+                        .logarithmic(true)
+                        .text("lamp height [m]"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut settings.screen_distance, 1e-4..=10.0)
+                        .logarithmic(true)
+                        .text("screen distance [m]"),
+                );
+            }
+
+            ui.heading("Colors");
+            egui::ComboBox::from_label("mode")
+                .selected_text(settings.mode.name())
+                .show_ui(ui, |ui| {
+                    for mode in vis::Mode::ALL {
+                        if ui
+                            .selectable_label(settings.mode == mode, mode.name())
+                            .clicked()
+                        {
+                            settings.set_mode(mode);
+                        }
+                    }
+                });
+            if settings.mode == vis::Mode::Decibel {
+                ui.add(egui::Slider::new(&mut settings.db_range, 10.0..=200.0).text("range [dB]"));
+            }
+            egui::ComboBox::from_label("range")
+                .selected_text(settings.auto_range.name())
+                .show_ui(ui, |ui| {
+                    for auto_range in range::AutoRange::ALL {
+                        ui.selectable_value(
+                            &mut settings.auto_range,
+                            auto_range,
+                            auto_range.name(),
+                        );
+                    }
+                });
+            if settings.auto_range == range::AutoRange::Percentile {
+                ui.add(
+                    egui::Slider::new(&mut settings.percentile, 50.0..=100.0).text("percentile"),
+                );
+            }
+            let clamp = ui.add(
+                egui::Slider::new(&mut settings.clamp, 1e-6..=1e2)
+                    .logarithmic(true)
+                    .text("clamp"),
+            );
+            if clamp.changed() {
+                settings.auto_range = range::AutoRange::Manual;
+            }
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut settings.show_contours, "contours");
+                ui.add_enabled(
+                    settings.show_contours,
+                    egui::Slider::new(&mut settings.contours, 1..=40).text("bands"),
+                );
+            });
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_label("colormap")
+                    .selected_text(&settings.colormap.name)
+                    .show_ui(ui, |ui| {
+                        for &name in colormap::Colormap::NAMES {
+                            if ui
+                                .selectable_label(settings.colormap.name == name, name)
+                                .clicked()
+                            {
+                                settings.colormap = colormap::Colormap::named(name).unwrap();
+                            }
+                        }
+                    });
+                if ui.button("reverse").clicked() {
+                    settings.colormap = settings.colormap.clone().reversed();
+                }
+            });
+        });
+    }
+}
@@ -0,0 +1,305 @@
+use crate::texture;
+use glam::{Mat4, Vec3};
+use wgpu::util::DeviceExt;
+
+/// Orbit camera looking at the center of the surface.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera {
+    /// Rotation around the vertical axis in rad
+    pub yaw: f32,
+    /// Elevation above the rest plane in rad
+    pub pitch: f32,
+    pub distance: f32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            yaw: 0.0,
+            pitch: 0.6,
+            distance: 3.0,
+        }
+    }
+}
+
+impl Camera {
+    const FOV: f32 = std::f32::consts::FRAC_PI_4;
+    const MIN_DISTANCE: f32 = 0.2;
+    const MAX_DISTANCE: f32 = 20.0;
+    /// Keeps the camera from flipping over the poles
+    const MAX_PITCH: f32 = 1.5;
+
+    /// Orbit by the given angles in rad.
+    pub fn rotate(&mut self, yaw: f32, pitch: f32) {
+        self.yaw += yaw;
+        self.pitch = (self.pitch + pitch).clamp(-Self::MAX_PITCH, Self::MAX_PITCH);
+    }
+
+    /// Move closer by `factor`, or away for factors below 1.
+    pub fn zoom(&mut self, factor: f32) {
+        self.distance = (self.distance / factor).clamp(Self::MIN_DISTANCE, Self::MAX_DISTANCE);
+    }
+
+    fn eye(&self) -> Vec3 {
+        self.distance
+            * Vec3::new(
+                self.pitch.cos() * self.yaw.sin(),
+                self.pitch.sin(),
+                self.pitch.cos() * self.yaw.cos(),
+            )
+    }
+
+    fn view_proj(&self, aspect_ratio: f32) -> Mat4 {
+        let proj = Mat4::perspective_rh(Self::FOV, aspect_ratio, 0.01, 100.0);
+        let view = Mat4::look_at_rh(self.eye(), Vec3::ZERO, Vec3::Y);
+        proj * view
+    }
+}
+
+/// [`Camera`] and mesh parameters as laid out in the uniform buffer of `shader.wgsl`
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Zeroable, bytemuck::Pod)]
+struct CameraUniform {
+    view_proj: [[f32; 4]; 4],
+    extent: [f32; 2],
+    texel: [f32; 2],
+    height: f32,
+    _padding: [f32; 3],
+}
+
+/// The field drawn as a lit mesh displaced by its value.
+///
+/// Shares the bind group of the flat view and samples the field texture in
+/// the vertex stage, so only the camera has to be uploaded per frame.
+pub struct HeightField {
+    render_pipeline: wgpu::RenderPipeline,
+    camera_buffer: wgpu::Buffer,
+    camera_bind_group: wgpu::BindGroup,
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    num_indices: u32,
+    /// Number of vertices in each direction
+    mesh_dim: (u32, u32),
+    depth_texture: texture::Texture,
+    pub camera: Camera,
+}
+
+impl HeightField {
+    /// Upper limit for the vertices along each side, larger fields are subsampled
+    const MAX_MESH_DIM: u32 = 512;
+
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        shader: &wgpu::ShaderModule,
+        field_bind_group_layout: &wgpu::BindGroupLayout,
+        dim: (u32, u32),
+        resolution: (u32, u32),
+    ) -> Self {
+        let camera_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Camera Buffer"),
+            size: std::mem::size_of::<CameraUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let camera_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+                label: Some("camera_bind_group_layout"),
+            });
+
+        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &camera_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: camera_buffer.as_entire_binding(),
+            }],
+            label: Some("camera_bind_group"),
+        });
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Surface Pipeline Layout"),
+                bind_group_layouts: &[field_bind_group_layout, &camera_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Surface Pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "vs_surface",
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &wgpu::vertex_attr_array![0 => Float32x2],
+                }],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: "fs_surface",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                // the surface can be seen from below
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: texture::Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        });
+
+        let mesh_dim = (
+            dim.0.clamp(2, Self::MAX_MESH_DIM),
+            dim.1.clamp(2, Self::MAX_MESH_DIM),
+        );
+        let (vertices, indices) = Self::mesh(mesh_dim);
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Surface Vertex Buffer"),
+            contents: bytemuck::cast_slice(&vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Surface Index Buffer"),
+            contents: bytemuck::cast_slice(&indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        Self {
+            render_pipeline,
+            camera_buffer,
+            camera_bind_group,
+            vertex_buffer,
+            index_buffer,
+            num_indices: indices.len() as u32,
+            mesh_dim,
+            depth_texture: texture::Texture::depth_texture(device, "depth texture", resolution),
+            camera: Camera::default(),
+        }
+    }
+
+    /// Regular grid of texture coordinates spanning the field, two triangles per quad.
+    fn mesh(dim: (u32, u32)) -> (Vec<[f32; 2]>, Vec<u32>) {
+        let vertices = (0..dim.1)
+            .flat_map(|j| {
+                (0..dim.0)
+                    .map(move |i| [i as f32 / (dim.0 - 1) as f32, j as f32 / (dim.1 - 1) as f32])
+            })
+            .collect();
+        let indices = (0..dim.1 - 1)
+            .flat_map(|j| {
+                (0..dim.0 - 1).flat_map(move |i| {
+                    let n = j * dim.0 + i;
+                    [n, n + dim.0, n + 1, n + 1, n + dim.0, n + dim.0 + 1]
+                })
+            })
+            .collect();
+        (vertices, indices)
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, resolution: (u32, u32)) {
+        self.depth_texture = texture::Texture::depth_texture(device, "depth texture", resolution);
+    }
+
+    /// Upload the camera for a target of `resolution` and a field of
+    /// `aspect_ratio`, values at the end of the colormap are raised by `height`.
+    pub fn prepare(
+        &self,
+        queue: &wgpu::Queue,
+        resolution: (u32, u32),
+        aspect_ratio: f32,
+        height: f32,
+    ) {
+        // the longer side spans [-1, 1]
+        let extent = if aspect_ratio > 1.0 {
+            [1.0, 1.0 / aspect_ratio]
+        } else {
+            [aspect_ratio, 1.0]
+        };
+        let uniform = CameraUniform {
+            view_proj: self
+                .camera
+                .view_proj(resolution.0 as f32 / resolution.1.max(1) as f32)
+                .to_cols_array_2d(),
+            extent,
+            texel: [
+                1.0 / (self.mesh_dim.0 - 1) as f32,
+                1.0 / (self.mesh_dim.1 - 1) as f32,
+            ],
+            height,
+            _padding: [0.0; 3],
+        };
+        queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    /// Depth attachment the surface has to be drawn with.
+    pub fn depth_attachment(&self) -> wgpu::RenderPassDepthStencilAttachment<'_> {
+        wgpu::RenderPassDepthStencilAttachment {
+            view: &self.depth_texture.view,
+            depth_ops: Some(wgpu::Operations {
+                load: wgpu::LoadOp::Clear(1.0),
+                store: wgpu::StoreOp::Discard,
+            }),
+            stencil_ops: None,
+        }
+    }
+
+    pub fn render<'pass>(
+        &'pass self,
+        render_pass: &mut wgpu::RenderPass<'pass>,
+        field_bind_group: &'pass wgpu::BindGroup,
+    ) {
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, field_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        render_pass.draw_indexed(0..self.num_indices, 0, 0..1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mesh() {
+        let (vertices, indices) = HeightField::mesh((3, 2));
+        assert_eq!(vertices.len(), 6);
+        assert_eq!(vertices[0], [0.0, 0.0]);
+        assert_eq!(vertices[5], [1.0, 1.0]);
+        assert_eq!(indices.len(), 2 * 6);
+        assert!(indices.iter().all(|&i| (i as usize) < vertices.len()));
+    }
+}
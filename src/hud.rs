@@ -0,0 +1,131 @@
+use glyphon::{
+    Attrs, Buffer, Color, Family, FontSystem, Metrics, Resolution, Shaping, SwashCache, TextArea,
+    TextAtlas, TextBounds, TextRenderer,
+};
+
+/// A block of text placed at a position in physical pixels.
+pub struct Label {
+    pub text: String,
+    /// Top left corner of the text
+    pub position: (f32, f32),
+}
+
+impl Label {
+    /// Estimated size of the text in pixels, assuming a monospace font.
+    pub fn size(&self) -> (f32, f32) {
+        let columns = self.text.lines().map(|l| l.chars().count()).max();
+        let lines = self.text.lines().count();
+        (
+            columns.unwrap_or(0) as f32 * Hud::FONT_SIZE * 0.6,
+            lines as f32 * Hud::LINE_HEIGHT,
+        )
+    }
+}
+
+/// Text overlay drawn on top of the simulation.
+pub struct Hud {
+    font_system: FontSystem,
+    cache: SwashCache,
+    atlas: TextAtlas,
+    renderer: TextRenderer,
+    /// One layout buffer per label, reused between frames
+    buffers: Vec<Buffer>,
+}
+
+impl Hud {
+    const FONT_SIZE: f32 = 18.0;
+    const LINE_HEIGHT: f32 = 22.0;
+
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, format: wgpu::TextureFormat) -> Self {
+        let font_system = FontSystem::new();
+        let cache = SwashCache::new();
+        let mut atlas = TextAtlas::new(device, queue, format);
+        let renderer =
+            TextRenderer::new(&mut atlas, device, wgpu::MultisampleState::default(), None);
+
+        Self {
+            font_system,
+            cache,
+            atlas,
+            renderer,
+            buffers: vec![],
+        }
+    }
+
+    /// Lay out `labels` and upload the glyphs needed to draw them.
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        resolution: (u32, u32),
+        labels: &[Label],
+    ) {
+        while self.buffers.len() < labels.len() {
+            let buffer = Buffer::new(
+                &mut self.font_system,
+                Metrics::new(Self::FONT_SIZE, Self::LINE_HEIGHT),
+            );
+            self.buffers.push(buffer);
+        }
+
+        for (buffer, label) in self.buffers.iter_mut().zip(labels) {
+            buffer.set_size(
+                &mut self.font_system,
+                resolution.0 as f32,
+                resolution.1 as f32,
+            );
+            buffer.set_text(
+                &mut self.font_system,
+                &label.text,
+                Attrs::new().family(Family::Monospace),
+                Shaping::Basic,
+            );
+            buffer.shape_until_scroll(&mut self.font_system);
+        }
+
+        let text_areas = self
+            .buffers
+            .iter()
+            .zip(labels)
+            .map(|(buffer, label)| TextArea {
+                buffer,
+                left: label.position.0,
+                top: label.position.1,
+                scale: 1.0,
+                bounds: TextBounds {
+                    left: 0,
+                    top: 0,
+                    right: resolution.0 as i32,
+                    bottom: resolution.1 as i32,
+                },
+                default_color: Color::rgb(255, 255, 255),
+            });
+
+        let result = self.renderer.prepare(
+            device,
+            queue,
+            &mut self.font_system,
+            &mut self.atlas,
+            Resolution {
+                width: resolution.0,
+                height: resolution.1,
+            },
+            text_areas,
+            &mut self.cache,
+        );
+        if let Err(e) = result {
+            log::warn!("Failed to prepare HUD: {}", e);
+        }
+    }
+
+    pub fn render<'pass>(&'pass self, render_pass: &mut wgpu::RenderPass<'pass>) {
+        if let Err(e) = self.renderer.render(&self.atlas, render_pass) {
+            log::warn!("Failed to render HUD: {}", e);
+        }
+    }
+
+    /// Free glyphs from the atlas that were not used in the last frame.
+    pub fn trim(&mut self) {
+        self.atlas.trim();
+    }
+}
@@ -1,14 +1,29 @@
 use clap::Parser;
 use winit::{
-    event::{ElementState, Event, KeyEvent, WindowEvent},
+    event::{ElementState, Event, KeyEvent, MouseButton, MouseScrollDelta, WindowEvent},
     event_loop::EventLoop,
     keyboard::{KeyCode, PhysicalKey},
     window::Window,
 };
 
+mod colormap;
+mod flux;
+mod gpu;
+mod gui;
+mod heightfield;
+mod hud;
+mod overlay;
+mod pacing;
+mod probe;
+mod range;
+mod scene;
+mod screen;
 mod sim;
+mod spectrum;
+mod stats;
 mod texture;
 mod vis;
+mod worker;
 
 #[derive(Parser, Debug)]
 struct Args {
@@ -27,16 +42,206 @@ struct Args {
     /// Time step in seconds
     #[arg(long, default_value_t = 1e-3)]
     dt: f64,
+    /// How many steps are advanced per frame
+    #[arg(long, value_enum, default_value_t = pacing::Pacing::Steps)]
+    pacing: pacing::Pacing,
+    /// Steps per frame with the steps pacing
+    #[arg(long, default_value_t = 1)]
+    steps_per_frame: u32,
+    /// Simulated seconds per wall clock second with the realtime pacing
+    #[arg(long, default_value_t = 1.0)]
+    rate: f64,
+    /// Frame rate held by the adaptive pacing
+    #[arg(long, default_value_t = 30.0)]
+    target_fps: f64,
+    /// Damping coefficient in 1/s
+    #[arg(long, default_value_t = 0.0)]
+    damping: f64,
+    /// Angular frequency of the source in rad/s
+    #[arg(long, default_value_t = 5.0)]
+    omega: f64,
+    /// Amplitude of the source
+    #[arg(long, default_value_t = 1.0)]
+    amplitude: f64,
+    /// Position `x,y` in m of a probe recording the field, may be repeated
+    #[arg(long = "probe", value_name = "X,Y")]
+    probes: Vec<probe::Probe>,
+    /// Ends `x1,y1,x2,y2` in m of a line integrating the intensity over time
+    /// since the last reset, plotted and saved with the probes
+    #[arg(long, value_name = "X1,Y1,X2,Y2")]
+    screen: Option<screen::Screen>,
+    /// Compare the intensity on the screen to the Fraunhofer diffraction of
+    /// the double slit
+    #[arg(long)]
+    fraunhofer: bool,
+    /// Line `x1,y1,x2,y2` in m measuring the energy flux through it, the
+    /// forward direction is `+x` for a line drawn downwards. May be repeated
+    #[arg(long = "flux-line", value_name = "X1,Y1,X2,Y2")]
+    flux_lines: Vec<flux::Monitor>,
+    /// Box between the corners `x1,y1,x2,y2` in m measuring the energy flux
+    /// out of it. May be repeated, numbered after the lines
+    #[arg(long = "flux-box", value_name = "X1,Y1,X2,Y2", value_parser = flux::Monitor::parse_box)]
+    flux_boxes: Vec<flux::Monitor>,
+    /// Window applied to the probe samples before their spectra are computed
+    #[arg(long, value_enum, default_value_t = spectrum::Window::Hann)]
+    window: spectrum::Window,
+    /// Minimum length of the zero padded probe samples relative to the
+    /// recorded ones
+    #[arg(long, default_value_t = 4)]
+    zero_padding: usize,
+    /// Arrangement of media and walls
+    #[arg(long, value_enum, default_value_t = scene::Scene::Empty)]
+    scene: scene::Scene,
+    /// Time in seconds after which the accumulated statistics forget the
+    /// field, `inf` accumulates everything since the last reset
+    #[arg(long, default_value_t = 5.0)]
+    average_time: f64,
+    /// Quantity shown by the visualizer
+    #[arg(long, value_enum, default_value_t = vis::Mode::Field)]
+    mode: vis::Mode,
+    /// Value mapped to the ends of the colormap
+    #[arg(long, default_value_t = 1.0)]
+    clamp: f64,
+    /// Automatic choice of the value mapped to the ends of the colormap
+    #[arg(long, value_enum, default_value_t = range::AutoRange::Manual)]
+    auto_range: range::AutoRange,
+    /// Percentile of the magnitudes used by the percentile auto range
+    #[arg(long, default_value_t = 99.0)]
+    percentile: f64,
+    /// Name of a built in colormap, optionally suffixed with `_r` to reverse
+    /// it, or path to a CSV/LUT file with one color per line. Defaults to a
+    /// colorblind safe colormap suited for the mode
+    #[arg(long)]
+    colormap: Option<colormap::Colormap>,
+    /// Number of bands the color range is split into by contour lines, even
+    /// numbers include the nodal lines of the field
+    #[arg(long, default_value_t = 10)]
+    contours: u32,
+    /// Draw contour lines from the start
+    #[arg(long)]
+    show_contours: bool,
+    /// How the field is drawn
+    #[arg(long, value_enum, default_value_t = vis::RenderMode::Flat)]
+    render_mode: vis::RenderMode,
+    /// Height of the surface at the ends of the colormap, relative to the
+    /// longer side of the simulation
+    #[arg(long, default_value_t = 0.2)]
+    height: f64,
+    /// Height of the lamp above the water in the ripple tank in m
+    #[arg(long, default_value_t = 10.0)]
+    lamp_height: f64,
+    /// Distance of the screen below the water in the ripple tank in m
+    #[arg(long, default_value_t = 0.05)]
+    screen_distance: f64,
+    /// Graphics API to use
+    #[arg(long, value_enum, default_value_t = gpu::Backend::All)]
+    backend: gpu::Backend,
+    /// Which GPU to prefer if there are several
+    #[arg(long, value_enum, default_value_t = gpu::PowerPreference::None)]
+    power_preference: gpu::PowerPreference,
+    /// Draw with a software adapter
+    #[arg(long)]
+    fallback_adapter: bool,
+    /// How frames are handed to the display
+    #[arg(long, value_enum, default_value_t = gpu::PresentMode::Vsync)]
+    present_mode: gpu::PresentMode,
+    /// Frames the GPU may queue ahead of the display, lower values reduce
+    /// input lag
+    #[arg(long, default_value_t = 2)]
+    frame_latency: u32,
+    /// Width in pixels of the images saved with E, the height follows the
+    /// aspect ratio of the simulation
+    #[arg(long, default_value_t = 3840)]
+    export_width: u32,
+    /// Run this many steps without a window, save the frame as a PNG of
+    /// `--export-width` and exit
+    #[arg(long, value_name = "STEPS")]
+    headless: Option<u32>,
+}
+
+impl Args {
+    fn gpu_options(&self) -> gpu::Options {
+        gpu::Options {
+            backend: self.backend,
+            power_preference: self.power_preference,
+            force_fallback_adapter: self.fallback_adapter,
+            present_mode: self.present_mode,
+            frame_latency: self.frame_latency,
+        }
+    }
+
+    fn analysis(&self) -> worker::Analysis {
+        let slits = self.scene.slits((self.x, self.y));
+        if self.fraunhofer && slits.is_none() {
+            log::warn!("The {:?} scene has no slits to compare to", self.scene);
+        }
+        worker::Analysis {
+            spectrum: spectrum::Settings {
+                window: self.window,
+                zero_padding: self.zero_padding,
+            },
+            slits: slits.filter(|_| self.fraunhofer),
+        }
+    }
+
+    fn controls(&self, sim: &sim::Simulation) -> gui::Controls {
+        gui::Controls {
+            params: sim.parameters(),
+            dt: self.dt,
+            pacing: self.pacing(),
+        }
+    }
+
+    fn vis_settings(&self) -> vis::Settings {
+        vis::Settings {
+            colormap: self
+                .colormap
+                .clone()
+                .unwrap_or_else(|| self.mode.default_colormap()),
+            mode: self.mode,
+            db_range: 60.0,
+            auto_range: self.auto_range,
+            percentile: self.percentile,
+            clamp: self.clamp,
+            render_mode: self.render_mode,
+            show_medium: true,
+            contours: self.contours,
+            show_contours: self.show_contours,
+            height: self.height,
+            lamp_height: self.lamp_height,
+            screen_distance: self.screen_distance,
+            aspect_ratio: self.x / self.y,
+            size: (self.x, self.y),
+        }
+    }
+
+    /// Size of the images saved, following the aspect ratio of the simulation.
+    fn export_resolution(&self) -> (u32, u32) {
+        let height = self.export_width as f64 * self.y / self.x;
+        (self.export_width, height.round() as u32)
+    }
+
+    fn pacing(&self) -> pacing::Settings {
+        pacing::Settings {
+            pacing: self.pacing,
+            steps_per_frame: self.steps_per_frame.max(1),
+            rate: self.rate,
+            target_fps: self.target_fps,
+        }
+    }
 }
 
 #[pollster::main]
-async fn main() {
+async fn main() -> anyhow::Result<()> {
     env_logger::init();
     let args = Args::parse();
+    if let Some(steps) = args.headless {
+        return headless(&args, steps).await;
+    }
 
-    let event_loop = EventLoop::new().unwrap();
+    let event_loop = EventLoop::new()?;
     event_loop.set_control_flow(winit::event_loop::ControlFlow::Poll);
-    let window = Window::new(&event_loop).unwrap();
+    let window = Window::new(&event_loop)?;
 
     let aspect_ratio = args.x / args.y;
     let height = 1000;
@@ -45,79 +250,239 @@ async fn main() {
     let _ = window.request_inner_size(winit::dpi::PhysicalSize { width, height });
 
     log::info!("Creating Simulation");
-    let mut sim = sim::Simulation::new(&args);
+    let sim = sim::Simulation::new(&args);
     log::info!("Created Simulation");
 
-    let vis_settings = vis::Settings {
-        colors: (wgpu::Color::BLUE, wgpu::Color::RED),
-        clamp: 1.0,
-        aspect_ratio,
-    };
-
     log::info!("Creating Visualizer");
     let mut vis = vis::Visualizer::new(
         &window,
         (args.discretization, args.discretization),
-        vis_settings,
+        args.vis_settings(),
+        args.gpu_options(),
     )
-    .await;
+    .await?;
+    vis.set_medium(&sim.medium().wave_speed());
     log::info!("Created Visualizer");
+    println!("Using {}", gpu::describe_adapter(vis.adapter_info()));
 
-    let mut steps_per_frame = 1;
+    let mut paused = false;
+    let mut controls = args.controls(&sim);
+    let worker = worker::Worker::spawn(sim, controls, vis.mode(), args.analysis());
+    // the frame drawn until the simulation publishes a newer one
+    let mut frame: Option<worker::Frame> = None;
+    // what the frames were last asked to contain
+    let mut requested = (vis.mode(), vis.flux_visible());
 
-    let _ = event_loop.run(move |event, elwt| match event {
-        Event::WindowEvent {
-            event: WindowEvent::CloseRequested,
-            ..
-        } => {
-            log::info!("The close button was pressed; stopping");
-            elwt.exit();
-        }
-        Event::AboutToWait => {
-            log::debug!(
-                "sim time: {:.4e} | energy: {:.4e}",
-                &sim.time(),
-                &sim.energy()
-            );
-            let field = sim.multi_step(steps_per_frame, args.dt);
-            vis.render(field);
-        }
-        Event::WindowEvent {
-            event: WindowEvent::RedrawRequested,
-            ..
-        } => {}
-        Event::WindowEvent {
-            event: WindowEvent::Resized(physical_size),
+    event_loop.run(move |event, elwt| {
+        if let Event::WindowEvent {
+            event: ref window_event,
             ..
-        } => {
-            vis.resize(physical_size);
+        } = event
+        {
+            if vis.handle_event(window_event) {
+                return;
+            }
         }
-        Event::WindowEvent {
-            event:
-                WindowEvent::KeyboardInput {
-                    event:
-                        KeyEvent {
-                            state: ElementState::Pressed,
-                            physical_key: PhysicalKey::Code(code),
-                            ..
-                        },
-                    ..
-                },
-            ..
-        } => match code {
-            KeyCode::ArrowUp => {
-                steps_per_frame += 1;
-                log::info!("steps_per_frame: {}", steps_per_frame);
+        match event {
+            Event::WindowEvent {
+                event: WindowEvent::CloseRequested,
+                ..
+            } => {
+                log::info!("The close button was pressed; stopping");
+                elwt.exit();
             }
-            KeyCode::ArrowDown => {
-                if steps_per_frame == 1 {
+            Event::AboutToWait => {
+                if let Some(newer) = worker.try_frame() {
+                    frame = Some(newer);
+                }
+                let Some(frame) = &frame else {
                     return;
+                };
+                if requested.0 != vis.mode() {
+                    worker.send(worker::Command::Show(vis.mode()));
+                }
+                if requested.1 != vis.flux_visible() {
+                    worker.send(worker::Command::ShowFlux(vis.flux_visible()));
+                }
+                requested = (vis.mode(), vis.flux_visible());
+                vis.set_probes(&frame.probes);
+                vis.set_profile(frame.profile.as_ref());
+                vis.set_flux(frame.flux.as_ref());
+                vis.set_monitors(&frame.monitors);
+                let status = frame.status(controls.pacing, paused);
+                let previous = controls;
+                if let Err(e) = vis.render(&frame.data, &status, &mut controls) {
+                    log::error!("{}", e);
+                    elwt.exit();
                 }
-                steps_per_frame -= 1;
-                log::info!("steps_per_frame: {}", steps_per_frame);
+                if controls != previous {
+                    worker.send(worker::Command::Controls(controls));
+                }
+            }
+            Event::WindowEvent {
+                event: WindowEvent::RedrawRequested,
+                ..
+            } => {}
+            Event::WindowEvent {
+                event: WindowEvent::Resized(physical_size),
+                ..
+            } => {
+                vis.resize(physical_size);
+            }
+            Event::WindowEvent {
+                event: WindowEvent::CursorMoved { position, .. },
+                ..
+            } => {
+                vis.set_cursor(Some(position));
+            }
+            Event::WindowEvent {
+                event: WindowEvent::CursorLeft { .. },
+                ..
+            } => {
+                vis.set_cursor(None);
+            }
+            Event::WindowEvent {
+                event: WindowEvent::MouseWheel { delta, .. },
+                ..
+            } => {
+                let lines = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => y as f64,
+                    MouseScrollDelta::PixelDelta(position) => position.y / 50.0,
+                };
+                vis.zoom(1.1f64.powf(lines));
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::MouseInput {
+                        state,
+                        button: MouseButton::Left,
+                        ..
+                    },
+                ..
+            } => {
+                vis.set_dragging(state == ElementState::Pressed);
             }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::MouseInput {
+                        state: ElementState::Pressed,
+                        button: MouseButton::Right,
+                        ..
+                    },
+                ..
+            } => {
+                if let Some(position) = vis.cursor_position() {
+                    worker.send(worker::Command::AddProbe(position));
+                    log::info!("probe at ({:.3} m, {:.3} m)", position.0, position.1);
+                }
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        event:
+                            KeyEvent {
+                                state: ElementState::Pressed,
+                                physical_key: PhysicalKey::Code(code),
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => match code {
+                KeyCode::ArrowUp => {
+                    controls.pacing.faster();
+                    worker.send(worker::Command::Controls(controls));
+                    log::info!("pacing: {}", controls.pacing.describe());
+                }
+                KeyCode::ArrowDown => {
+                    controls.pacing.slower();
+                    worker.send(worker::Command::Controls(controls));
+                    log::info!("pacing: {}", controls.pacing.describe());
+                }
+                KeyCode::KeyP => {
+                    controls.pacing.pacing = controls.pacing.pacing.next();
+                    worker.send(worker::Command::Controls(controls));
+                    log::info!("pacing: {}", controls.pacing.describe());
+                }
+                KeyCode::Space => {
+                    paused = !paused;
+                    worker.send(worker::Command::Pause(paused));
+                    log::info!("paused: {}", paused);
+                }
+                KeyCode::ArrowRight => {
+                    if !paused {
+                        return;
+                    }
+                    worker.send(worker::Command::Step);
+                }
+                KeyCode::KeyR => {
+                    worker.send(worker::Command::Reset);
+                    vis.reset_range();
+                    log::info!("reset simulation");
+                }
+                KeyCode::KeyB => worker.send(worker::Command::Reverse),
+                KeyCode::KeyH => vis.toggle_hud(),
+                KeyCode::KeyL => vis.toggle_legend(),
+                KeyCode::KeyG => vis.toggle_gui(),
+                KeyCode::KeyC => vis.cycle_colormap(),
+                KeyCode::KeyM => vis.cycle_mode(),
+                KeyCode::KeyA => vis.cycle_auto_range(),
+                KeyCode::KeyV => vis.reset_view(),
+                KeyCode::KeyT => vis.cycle_render_mode(),
+                KeyCode::KeyO => vis.toggle_medium(),
+                KeyCode::KeyK => vis.toggle_contours(),
+                KeyCode::KeyF => vis.toggle_flux(),
+                KeyCode::Equal => vis.scale_clamp(0.5),
+                KeyCode::Minus => vis.scale_clamp(2.0),
+                KeyCode::KeyS => worker.send(worker::Command::Export),
+                KeyCode::KeyE => {
+                    let Some(frame) = &frame else {
+                        return;
+                    };
+                    let resolution = args.export_resolution();
+                    let path = format!("wave-simmers-{:.3}s.png", frame.time);
+                    let status = frame.status(controls.pacing, paused);
+                    match vis.render_image(&frame.data, &status, resolution) {
+                        Ok(image) => match image.save(&path) {
+                            Ok(()) => log::info!("saved {}", path),
+                            Err(e) => log::error!("Failed to save {}: {}", path, e),
+                        },
+                        Err(e) => log::error!("{}", e),
+                    }
+                }
+                _ => (),
+            },
             _ => (),
-        },
-        _ => (),
-    });
+        }
+    })?;
+    Ok(())
+}
+
+/// Simulate `steps` steps and save the frame without opening a window.
+async fn headless(args: &Args, steps: u32) -> anyhow::Result<()> {
+    let sim = sim::Simulation::new(args);
+    let controls = args.controls(&sim);
+    let resolution = args.export_resolution();
+
+    let mut vis = vis::Visualizer::offscreen(
+        (args.discretization, args.discretization),
+        args.vis_settings(),
+        args.gpu_options(),
+        resolution,
+    )
+    .await?;
+    vis.set_medium(&sim.medium().wave_speed());
+    println!("Using {}", gpu::describe_adapter(vis.adapter_info()));
+
+    let frame = worker::run_to_frame(sim, controls, vis.mode(), args.analysis(), steps);
+    vis.set_probes(&frame.probes);
+    vis.set_profile(frame.profile.as_ref());
+    vis.set_monitors(&frame.monitors);
+    let status = frame.status(controls.pacing, false);
+    let image = vis.render_image(&frame.data, &status, resolution)?;
+
+    let path = format!("wave-simmers-{:.3}s.png", frame.time);
+    image.save(&path)?;
+    println!("Saved {}", path);
+    Ok(())
 }
@@ -0,0 +1,239 @@
+use wgpu::util::DeviceExt;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Zeroable, bytemuck::Pod)]
+struct Vertex {
+    position: [f32; 2],
+    color: [f32; 4],
+    t: f32,
+}
+
+impl Vertex {
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        use std::mem;
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<Vertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x2,
+                    offset: 0,
+                    shader_location: 0,
+                },
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32x4,
+                    offset: mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                },
+                wgpu::VertexAttribute {
+                    format: wgpu::VertexFormat::Float32,
+                    offset: mem::size_of::<[f32; 6]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                },
+            ],
+        }
+    }
+}
+
+/// Flat 2D geometry like legends and plots, drawn on top of the simulation.
+///
+/// Shapes are given in physical pixels with the origin in the top left
+/// corner and collected until the next [`Overlay::prepare`].
+pub struct Overlay {
+    render_pipeline: wgpu::RenderPipeline,
+    bind_group: wgpu::BindGroup,
+    vertices: Vec<Vertex>,
+    vertex_buffer: Option<(wgpu::Buffer, u32)>,
+}
+
+impl Overlay {
+    /// Marks a vertex with a plain color
+    const NO_COLORMAP: f32 = -1.0;
+
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        colormap_view: &wgpu::TextureView,
+        colormap_sampler: &wgpu::Sampler,
+    ) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some("overlay_bind_group_layout"),
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(colormap_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(colormap_sampler),
+                },
+            ],
+            label: Some("overlay_bind_group"),
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Overlay Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("overlay.wgsl").into()),
+        });
+
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Overlay Pipeline Layout"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Overlay Pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[Vertex::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                // shapes are emitted in either winding
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        });
+
+        Self {
+            render_pipeline,
+            bind_group,
+            vertices: vec![],
+            vertex_buffer: None,
+        }
+    }
+
+    /// Axis aligned rectangle with a plain color.
+    pub fn rect(&mut self, min: (f32, f32), max: (f32, f32), color: [f32; 4]) {
+        self.quad(
+            [min, (max.0, min.1), (min.0, max.1), max],
+            [color; 4],
+            [Self::NO_COLORMAP; 4],
+        );
+    }
+
+    /// Line of `width` from `from` to `to` with a plain color.
+    pub fn line(&mut self, from: (f32, f32), to: (f32, f32), width: f32, color: [f32; 4]) {
+        let length = (to.0 - from.0).hypot(to.1 - from.1);
+        if length == 0.0 {
+            return;
+        }
+        // half the width along the normal
+        let normal = (
+            (from.1 - to.1) / length * width / 2.0,
+            (to.0 - from.0) / length * width / 2.0,
+        );
+        self.quad(
+            [
+                (from.0 + normal.0, from.1 + normal.1),
+                (to.0 + normal.0, to.1 + normal.1),
+                (from.0 - normal.0, from.1 - normal.1),
+                (to.0 - normal.0, to.1 - normal.1),
+            ],
+            [color; 4],
+            [Self::NO_COLORMAP; 4],
+        );
+    }
+
+    /// Axis aligned rectangle showing the colormap from `t.0` at the bottom
+    /// to `t.1` at the top.
+    pub fn gradient(&mut self, min: (f32, f32), max: (f32, f32), t: (f32, f32)) {
+        self.quad(
+            [min, (max.0, min.1), (min.0, max.1), max],
+            [[0.0; 4]; 4],
+            [t.1, t.1, t.0, t.0],
+        );
+    }
+
+    /// Quad with corners ordered top left, top right, bottom left, bottom right.
+    fn quad(&mut self, corners: [(f32, f32); 4], colors: [[f32; 4]; 4], t: [f32; 4]) {
+        for i in [0, 2, 1, 1, 2, 3] {
+            self.vertices.push(Vertex {
+                position: [corners[i].0, corners[i].1],
+                color: colors[i],
+                t: t[i],
+            });
+        }
+    }
+
+    /// Upload the shapes collected since the last call for a target of `resolution`.
+    pub fn prepare(&mut self, device: &wgpu::Device, resolution: (u32, u32)) {
+        if self.vertices.is_empty() {
+            self.vertex_buffer = None;
+            return;
+        }
+
+        // pixels to clip space
+        let (width, height) = (resolution.0 as f32, resolution.1 as f32);
+        for vertex in &mut self.vertices {
+            vertex.position = [
+                2.0 * vertex.position[0] / width - 1.0,
+                1.0 - 2.0 * vertex.position[1] / height,
+            ];
+        }
+
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Overlay Vertex Buffer"),
+            contents: bytemuck::cast_slice(&self.vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        self.vertex_buffer = Some((vertex_buffer, self.vertices.len() as u32));
+        self.vertices.clear();
+    }
+
+    pub fn render<'pass>(&'pass self, render_pass: &mut wgpu::RenderPass<'pass>) {
+        let Some((vertex_buffer, num_vertices)) = &self.vertex_buffer else {
+            return;
+        };
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+        render_pass.draw(0..*num_vertices, 0..1);
+    }
+}
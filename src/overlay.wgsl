@@ -0,0 +1,39 @@
+// Overlay geometry given in clip space, either with a plain color or a
+// position on the colormap
+
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+    @location(1) color: vec4<f32>,
+    // position on the colormap, negative to use the plain color
+    @location(2) t: f32,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+    @location(1) t: f32,
+}
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(model.position, 0.0, 1.0);
+    out.color = model.color;
+    out.t = model.t;
+    return out;
+}
+
+@group(0) @binding(0)
+var t_colormap: texture_2d<f32>;
+@group(0) @binding(1)
+var s_colormap: sampler;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let size = f32(textureDimensions(t_colormap).x);
+    let x = (clamp(in.t, 0.0, 1.0) * (size - 1.0) + 0.5) / size;
+    let mapped = textureSample(t_colormap, s_colormap, vec2<f32>(x, 0.5));
+    return select(mapped, in.color, in.t < 0.0);
+}
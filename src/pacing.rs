@@ -0,0 +1,181 @@
+/// How many steps the simulation advances per frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Pacing {
+    /// A fixed number of steps per frame
+    #[default]
+    Steps,
+    /// A fixed number of simulated seconds per wall clock second
+    Realtime,
+    /// As many steps as possible while holding a target frame rate
+    Adaptive,
+}
+
+impl Pacing {
+    pub const ALL: [Pacing; 3] = [Pacing::Steps, Pacing::Realtime, Pacing::Adaptive];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Pacing::Steps => "steps",
+            Pacing::Realtime => "realtime",
+            Pacing::Adaptive => "adaptive",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        Self::ALL[(self as usize + 1) % Self::ALL.len()]
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Settings {
+    pub pacing: Pacing,
+    /// Steps per frame in [`Pacing::Steps`]
+    pub steps_per_frame: u32,
+    /// Simulated seconds per wall clock second in [`Pacing::Realtime`]
+    pub rate: f64,
+    /// Frames per second held in [`Pacing::Adaptive`]
+    pub target_fps: f64,
+}
+
+impl Settings {
+    /// Speed up whatever the current pacing is controlled by.
+    pub fn faster(&mut self) {
+        match self.pacing {
+            Pacing::Steps => self.steps_per_frame += 1,
+            Pacing::Realtime => self.rate *= 2.0,
+            // fewer frames leave more time for steps
+            Pacing::Adaptive => self.target_fps = (self.target_fps - 5.0).max(5.0),
+        }
+    }
+
+    pub fn slower(&mut self) {
+        match self.pacing {
+            Pacing::Steps => self.steps_per_frame = self.steps_per_frame.saturating_sub(1).max(1),
+            Pacing::Realtime => self.rate /= 2.0,
+            Pacing::Adaptive => self.target_fps += 5.0,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self.pacing {
+            Pacing::Steps => format!("{} steps / frame", self.steps_per_frame),
+            Pacing::Realtime => format!("{:.3e} s/s", self.rate),
+            Pacing::Adaptive => format!("{:.0} fps target", self.target_fps),
+        }
+    }
+}
+
+/// Turns the elapsed wall clock time into a number of steps.
+#[derive(Debug)]
+pub struct Pacer {
+    /// Simulated time owed to the clock in [`Pacing::Realtime`]
+    accumulator: f64,
+    /// Unrounded steps per frame in [`Pacing::Adaptive`]
+    adaptive_steps: f64,
+}
+
+impl Default for Pacer {
+    fn default() -> Self {
+        Self {
+            accumulator: 0.0,
+            adaptive_steps: 1.0,
+        }
+    }
+}
+
+impl Pacer {
+    /// Longest wall clock time caught up with at once, a slower simulation
+    /// falls behind instead of stalling the frames
+    const MAX_LAG: f64 = 0.1;
+    /// Most steps taken at once in [`Pacing::Realtime`], so commands are
+    /// still handled between the batches of high rates
+    const MAX_STEPS: f64 = 1000.0;
+    /// Largest change of the adaptive steps per frame
+    const MAX_ADJUSTMENT: f64 = 1.25;
+
+    /// Steps to advance for a frame that took `elapsed` seconds of wall clock
+    /// time since the previous one.
+    pub fn steps(&mut self, settings: &Settings, elapsed: f64, dt: f64) -> u32 {
+        match settings.pacing {
+            Pacing::Steps => settings.steps_per_frame,
+            Pacing::Realtime => {
+                self.accumulator += settings.rate * elapsed.min(Self::MAX_LAG);
+                let steps = (self.accumulator / dt).floor().min(Self::MAX_STEPS);
+                // the rest is owed to the next batches, up to the longest lag
+                self.accumulator =
+                    (self.accumulator - steps * dt).min(settings.rate * Self::MAX_LAG);
+                steps as u32
+            }
+            Pacing::Adaptive => {
+                if elapsed > 0.0 {
+                    // the time of a frame grows about linearly with its steps
+                    let adjustment = (1.0 / settings.target_fps / elapsed)
+                        .clamp(1.0 / Self::MAX_ADJUSTMENT, Self::MAX_ADJUSTMENT);
+                    self.adaptive_steps = (self.adaptive_steps * adjustment).max(1.0);
+                }
+                self.adaptive_steps.round() as u32
+            }
+        }
+    }
+
+    /// Forget the time owed from before a pause.
+    pub fn reset(&mut self) {
+        self.accumulator = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_realtime() {
+        let settings = Settings {
+            pacing: Pacing::Realtime,
+            steps_per_frame: 1,
+            rate: 0.5,
+            target_fps: 30.0,
+        };
+        let mut pacer = Pacer::default();
+        let dt = 1e-3;
+
+        // half a second of simulated time per second in steps of 1 ms
+        let steps: u32 = (0..100).map(|_| pacer.steps(&settings, 0.01, dt)).sum();
+        assert!((499..=500).contains(&steps));
+
+        // long stalls are not caught up with
+        assert_eq!(pacer.steps(&settings, 10.0, dt), 50);
+
+        // high rates are split into batches
+        let settings = Settings {
+            rate: 100.0,
+            ..settings
+        };
+        pacer.reset();
+        assert_eq!(pacer.steps(&settings, 0.1, dt), 1000);
+        assert_eq!(pacer.steps(&settings, 0.0, dt), 1000);
+        // without owing more than the longest lag
+        for _ in 0..100 {
+            pacer.steps(&settings, 0.1, dt);
+        }
+        assert!(pacer.accumulator <= 10.0);
+    }
+
+    #[test]
+    fn test_adaptive() {
+        let settings = Settings {
+            pacing: Pacing::Adaptive,
+            steps_per_frame: 1,
+            rate: 1.0,
+            target_fps: 50.0,
+        };
+        let mut pacer = Pacer::default();
+
+        // frames taking 1 ms per step settle at 20 steps
+        let mut steps = 1;
+        for _ in 0..100 {
+            steps = pacer.steps(&settings, steps as f64 * 1e-3, 1e-3);
+        }
+        assert_eq!(steps, 20);
+    }
+}
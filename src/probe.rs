@@ -0,0 +1,111 @@
+use std::io::{self, Write};
+use std::str::FromStr;
+
+/// Point in the simulated domain recording the field over time.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Probe {
+    /// Position in m, measured from the top left corner
+    pub position: (f64, f64),
+    /// Time and field of every step since the probe was placed
+    pub samples: Vec<(f64, f64)>,
+}
+
+impl Probe {
+    pub fn new(position: (f64, f64)) -> Self {
+        Self {
+            position,
+            samples: vec![],
+        }
+    }
+
+    /// Append the field at time `t` to the samples.
+    pub fn record(&mut self, t: f64, field: &[f64], size: (f64, f64), discretization: u32) {
+        let u = interpolate(field, self.position, size, discretization);
+        self.samples.push((t, u));
+    }
+}
+
+/// Value of `field` at `position` in m on a grid of `discretization` cells
+/// along each side of a domain of `size` in m, interpolated bilinearly
+/// between the centers of the surrounding cells.
+pub fn interpolate(
+    field: &[f64],
+    position: (f64, f64),
+    size: (f64, f64),
+    discretization: u32,
+) -> f64 {
+    let n = discretization as usize;
+    // position in cells relative to the center of the first cell, positions
+    // near the edges take the value of the outermost cells
+    let cell = |x: f64, size: f64| {
+        let x = (x / size * n as f64 - 0.5).clamp(0.0, (n - 1) as f64);
+        let i = (x.floor() as usize).min(n.saturating_sub(2));
+        (i, (i + 1).min(n - 1), x - i as f64)
+    };
+    let (col0, col1, wx) = cell(position.0, size.0);
+    let (row0, row1, wy) = cell(position.1, size.1);
+    let at = |row: usize, col: usize| field[row * n + col];
+
+    let top = at(row0, col0) * (1.0 - wx) + at(row0, col1) * wx;
+    let bottom = at(row1, col0) * (1.0 - wx) + at(row1, col1) * wx;
+    top * (1.0 - wy) + bottom * wy
+}
+
+impl FromStr for Probe {
+    type Err = anyhow::Error;
+
+    /// Position in m given as `x,y`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (x, y) = s
+            .split_once(',')
+            .ok_or_else(|| anyhow::anyhow!("Expected a position `x,y`, got `{}`", s))?;
+        Ok(Self::new((x.trim().parse()?, y.trim().parse()?)))
+    }
+}
+
+/// Write the samples of all `probes` as CSV, one row per sample.
+pub fn write_csv(probes: &[Probe], mut writer: impl Write) -> io::Result<()> {
+    writeln!(writer, "probe,x,y,t,u")?;
+    for (i, probe) in probes.iter().enumerate() {
+        let (x, y) = probe.position;
+        for (t, u) in &probe.samples {
+            writeln!(writer, "{},{},{},{:e},{:e}", i + 1, x, y, t, u)?;
+        }
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interpolate() {
+        // cell centers at 0.5, 1.5 and 2.5 m
+        let field = [0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0];
+        let at = |x, y| interpolate(&field, (x, y), (3.0, 3.0), 3);
+
+        assert_eq!(at(0.5, 0.5), 0.0);
+        assert_eq!(at(1.5, 1.5), 4.0);
+        assert_eq!(at(1.0, 0.5), 0.5);
+        assert_eq!(at(2.0, 2.0), 6.0);
+        // clamped to the outermost cells
+        assert_eq!(at(3.0, 3.0), 8.0);
+        assert_eq!(at(0.0, 1.5), 3.0);
+    }
+
+    #[test]
+    fn test_csv() {
+        let mut probe: Probe = "1.5, 2".parse().unwrap();
+        assert_eq!(probe.position, (1.5, 2.0));
+        assert!("1.5".parse::<Probe>().is_err());
+
+        probe.samples = vec![(0.0, 1.0), (0.5, -0.25)];
+        let mut csv = vec![];
+        write_csv(&[probe], &mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "probe,x,y,t,u\n1,1.5,2,0e0,1e0\n1,1.5,2,5e-1,-2.5e-1\n"
+        );
+    }
+}
@@ -0,0 +1,137 @@
+/// How the value mapped to the ends of the colormap is chosen.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum AutoRange {
+    /// Keep the value set by hand
+    #[default]
+    Manual,
+    /// Largest magnitude seen since the last reset
+    RunningMax,
+    /// Percentile of the magnitudes in the current frame
+    Percentile,
+    /// Exponential moving average of the peak magnitude of each frame
+    PeakAverage,
+}
+
+impl AutoRange {
+    pub const ALL: [AutoRange; 4] = [
+        AutoRange::Manual,
+        AutoRange::RunningMax,
+        AutoRange::Percentile,
+        AutoRange::PeakAverage,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AutoRange::Manual => "manual",
+            AutoRange::RunningMax => "running max",
+            AutoRange::Percentile => "percentile",
+            AutoRange::PeakAverage => "peak average",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        Self::ALL[(self as usize + 1) % Self::ALL.len()]
+    }
+}
+
+/// Tracks the magnitudes of the shown frames to pick a color range.
+#[derive(Debug, Default)]
+pub struct RangeTracker {
+    running_max: f64,
+    peak_average: Option<f64>,
+}
+
+impl RangeTracker {
+    /// Weight of the newest frame in the peak average
+    const SMOOTHING: f64 = 0.05;
+    /// Number of values the percentile is estimated from
+    const PERCENTILE_SAMPLES: usize = 1 << 16;
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Feed the magnitudes of a frame, returns the new range unless it is
+    /// chosen by hand or no range could be determined yet.
+    pub fn update(&mut self, mode: AutoRange, percentile: f64, magnitudes: &[f64]) -> Option<f64> {
+        let range = match mode {
+            AutoRange::Manual => return None,
+            AutoRange::RunningMax => {
+                self.running_max = magnitudes.iter().fold(self.running_max, |a, &b| a.max(b));
+                self.running_max
+            }
+            AutoRange::Percentile => {
+                // estimate from a subset, a full sort of every frame is too slow
+                let stride = (magnitudes.len() / Self::PERCENTILE_SAMPLES).max(1);
+                let mut samples: Vec<f64> = magnitudes.iter().step_by(stride).copied().collect();
+                Self::percentile(&mut samples, percentile)?
+            }
+            AutoRange::PeakAverage => {
+                let peak = magnitudes.iter().fold(0.0f64, |a, &b| a.max(b));
+                let average = match self.peak_average {
+                    Some(average) => average + Self::SMOOTHING * (peak - average),
+                    None => peak,
+                };
+                self.peak_average = Some(average);
+                average
+            }
+        };
+
+        // an empty field has no range, keep the previous one
+        (range > 0.0 && range.is_finite()).then_some(range)
+    }
+
+    /// The `p`-th percentile of `values`, reorders `values`.
+    fn percentile(values: &mut [f64], p: f64) -> Option<f64> {
+        if values.is_empty() {
+            return None;
+        }
+        let rank = ((p / 100.0).clamp(0.0, 1.0) * (values.len() - 1) as f64).round() as usize;
+        let (_, value, _) = values.select_nth_unstable_by(rank, f64::total_cmp);
+        Some(*value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentile() {
+        let mut values: Vec<f64> = (0..=100).rev().map(|v| v as f64).collect();
+        assert_eq!(RangeTracker::percentile(&mut values, 99.0), Some(99.0));
+        assert_eq!(RangeTracker::percentile(&mut values, 50.0), Some(50.0));
+        assert_eq!(RangeTracker::percentile(&mut values, 100.0), Some(100.0));
+        assert_eq!(RangeTracker::percentile(&mut [], 50.0), None);
+    }
+
+    #[test]
+    fn test_update() {
+        let mut tracker = RangeTracker::default();
+        assert_eq!(tracker.update(AutoRange::Manual, 99.0, &[1.0]), None);
+        assert_eq!(tracker.update(AutoRange::RunningMax, 99.0, &[0.0]), None);
+        assert_eq!(
+            tracker.update(AutoRange::RunningMax, 99.0, &[2.0]),
+            Some(2.0)
+        );
+        assert_eq!(
+            tracker.update(AutoRange::RunningMax, 99.0, &[1.0]),
+            Some(2.0)
+        );
+
+        assert_eq!(
+            tracker.update(AutoRange::PeakAverage, 99.0, &[1.0]),
+            Some(1.0)
+        );
+        let average = tracker
+            .update(AutoRange::PeakAverage, 99.0, &[2.0])
+            .unwrap();
+        assert!(average > 1.0 && average < 2.0);
+
+        tracker.reset();
+        assert_eq!(
+            tracker.update(AutoRange::RunningMax, 99.0, &[1.0]),
+            Some(1.0)
+        );
+    }
+}
@@ -0,0 +1,166 @@
+/// Preset arrangement of media and walls around the source in the center.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Scene {
+    /// Free space
+    #[default]
+    Empty,
+    /// Wall with two slits to the right of the source
+    DoubleSlit,
+    /// Biconvex glass lens to the right of the source
+    Lens,
+}
+
+/// Properties of every cell of the simulated domain.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Medium {
+    /// Refractive index `c0 / c` of each cell
+    pub index: Vec<f64>,
+    /// Cells that hold the field at zero
+    pub walls: Vec<bool>,
+}
+
+impl Medium {
+    /// Free space of `cells` cells.
+    pub fn uniform(cells: usize) -> Self {
+        Self {
+            index: vec![1.0; cells],
+            walls: vec![false; cells],
+        }
+    }
+
+    /// Wave speed of each cell relative to `c0`, zero inside walls.
+    pub fn wave_speed(&self) -> Vec<f64> {
+        self.index
+            .iter()
+            .zip(&self.walls)
+            .map(|(&n, &wall)| if wall { 0.0 } else { 1.0 / n })
+            .collect()
+    }
+}
+
+/// Two equal slits in a thin wall, in m.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Slits {
+    /// Midpoint between the slits
+    pub center: (f64, f64),
+    /// Distance between the centers of the slits
+    pub separation: f64,
+    pub width: f64,
+}
+
+impl Slits {
+    /// Far field intensity at `point` relative to the maximum, for a wave of
+    /// `wavelength` arriving perpendicular to the wall.
+    pub fn fraunhofer(&self, point: (f64, f64), wavelength: f64) -> f64 {
+        let (dx, dy) = (point.0 - self.center.0, point.1 - self.center.1);
+        let sin = dy / dx.hypot(dy);
+        let k = std::f64::consts::PI * sin / wavelength;
+        let sinc = |x: f64| if x == 0.0 { 1.0 } else { x.sin() / x };
+        (k * self.separation).cos().powi(2) * sinc(k * self.width).powi(2)
+    }
+}
+
+impl Scene {
+    /// Refractive index of the lens
+    const GLASS: f64 = 1.5;
+    /// Position of the double slit wall along x, relative to the domain
+    const SLIT_WALL: f64 = 0.6;
+    /// Positions of the slits along y, relative to the domain
+    const SLITS: [f64; 2] = [0.4, 0.6];
+    /// Width of each slit, relative to the domain
+    const SLIT_WIDTH: f64 = 0.04;
+
+    /// Geometry of the slits in m, for scenes that have them.
+    pub fn slits(self, size: (f64, f64)) -> Option<Slits> {
+        match self {
+            Scene::DoubleSlit => Some(Slits {
+                center: (
+                    Self::SLIT_WALL * size.0,
+                    (Self::SLITS[0] + Self::SLITS[1]) / 2.0 * size.1,
+                ),
+                separation: (Self::SLITS[1] - Self::SLITS[0]) * size.1,
+                width: Self::SLIT_WIDTH * size.1,
+            }),
+            Scene::Empty | Scene::Lens => None,
+        }
+    }
+
+    /// Build the medium on a grid of `discretization` cells along each side
+    /// of a domain of `size` in m.
+    pub fn medium(self, size: (f64, f64), discretization: u32) -> Medium {
+        let n = discretization as usize;
+        let mut medium = Medium::uniform(n * n);
+
+        for row in 0..n {
+            for col in 0..n {
+                // cell center relative to the domain, in [0, 1]
+                let x = (col as f64 + 0.5) / n as f64;
+                let y = (row as f64 + 0.5) / n as f64;
+                let i = row * n + col;
+                match self {
+                    Scene::Empty => (),
+                    Scene::DoubleSlit => {
+                        let thickness = 2.0 / n as f64;
+                        let slit = |center: f64| (y - center).abs() < Self::SLIT_WIDTH / 2.0;
+                        medium.walls[i] = (x - Self::SLIT_WALL).abs() < thickness
+                            && !Self::SLITS.into_iter().any(slit);
+                    }
+                    Scene::Lens => {
+                        // intersection of two circles, in m to keep it round
+                        let radius = 0.3 * size.0.max(size.1);
+                        let half_thickness = 0.05 * size.0.max(size.1);
+                        let (px, py) = ((x - 0.7) * size.0, (y - 0.5) * size.1);
+                        let inside = |center: f64| (px - center).hypot(py) < radius;
+                        if inside(radius - half_thickness) && inside(half_thickness - radius) {
+                            medium.index[i] = Self::GLASS;
+                        }
+                    }
+                }
+            }
+        }
+        medium
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_medium() {
+        let empty = Scene::Empty.medium((10.0, 10.0), 100);
+        assert_eq!(empty, Medium::uniform(100 * 100));
+
+        let slits = Scene::DoubleSlit.medium((10.0, 10.0), 100);
+        let wall = |row: usize| slits.walls[row * 100 + 60];
+        assert!(wall(10) && wall(50) && wall(90));
+        assert!(!wall(40) && !wall(60));
+
+        let lens = Scene::Lens.medium((10.0, 10.0), 100);
+        assert_eq!(lens.index[50 * 100 + 70], Scene::GLASS);
+        assert_eq!(lens.index[50 * 100 + 50], 1.0);
+        assert_eq!(lens.wave_speed()[50 * 100 + 70], 1.0 / Scene::GLASS);
+    }
+
+    #[test]
+    fn test_fraunhofer() {
+        let slits = Scene::DoubleSlit.slits((10.0, 10.0)).unwrap();
+        assert_eq!(slits.center, (6.0, 5.0));
+        assert!(Scene::Lens.slits((10.0, 10.0)).is_none());
+
+        let wavelength = 0.5;
+        let at_angle = |sin: f64| {
+            let point = (
+                slits.center.0 + 1.0,
+                slits.center.1 + sin / (1.0 - sin * sin).sqrt(),
+            );
+            slits.fraunhofer(point, wavelength)
+        };
+        assert_eq!(at_angle(0.0), 1.0);
+        // first minimum of the interference, half a wavelength path difference
+        assert!(at_angle(wavelength / 2.0 / slits.separation) < 1e-12);
+        // second maximum, attenuated by the diffraction of each slit
+        let second = at_angle(wavelength / slits.separation);
+        assert!((second - 0.875).abs() < 1e-3);
+    }
+}
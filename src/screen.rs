@@ -0,0 +1,181 @@
+use crate::{probe, scene};
+use std::io::{self, Write};
+use std::str::FromStr;
+
+/// Line in the domain like the screen behind a double slit, see
+/// [`Detector`] for the intensity collected on it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Screen {
+    /// Ends of the line in m
+    pub start: (f64, f64),
+    pub end: (f64, f64),
+}
+
+/// Integrates the intensity `u^2` at evenly spaced points along a screen
+/// over time, weighing every moment since the last reset alike.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Detector {
+    pub screen: Screen,
+    /// Integral of `u^2` over time at each sample
+    integral: Vec<f64>,
+    /// Time integrated over
+    duration: f64,
+}
+
+/// Intensity along a screen.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Profile {
+    pub screen: Screen,
+    /// Distance of each sample from the start of the screen in m
+    pub distance: Vec<f64>,
+    /// Integral of `u^2` over time at each sample
+    pub integral: Vec<f64>,
+    /// Mean intensity `<u^2>` since the last reset at each sample
+    pub intensity: Vec<f64>,
+    /// Fraunhofer prediction of the mean intensity, scaled to its maximum
+    pub prediction: Option<Vec<f64>>,
+}
+
+impl Screen {
+    /// Points the intensity is sampled at
+    const SAMPLES: usize = 256;
+
+    pub fn length(&self) -> f64 {
+        (self.end.0 - self.start.0).hypot(self.end.1 - self.start.1)
+    }
+
+    /// Point at `f` in `[0, 1]` along the screen.
+    fn point(&self, f: f64) -> (f64, f64) {
+        (
+            self.start.0 + f * (self.end.0 - self.start.0),
+            self.start.1 + f * (self.end.1 - self.start.1),
+        )
+    }
+
+    /// Fractions of the length the samples lie at.
+    fn fractions() -> impl Iterator<Item = f64> {
+        (0..Self::SAMPLES).map(|i| i as f64 / (Self::SAMPLES - 1) as f64)
+    }
+}
+
+impl Detector {
+    pub fn new(screen: Screen) -> Self {
+        Self {
+            screen,
+            integral: vec![0.0; Screen::SAMPLES],
+            duration: 0.0,
+        }
+    }
+
+    /// Add the intensity of `field` over a step of `dt`, on a grid of
+    /// `discretization` cells along each side of a domain of `size` in m.
+    pub fn record(&mut self, field: &[f64], size: (f64, f64), discretization: u32, dt: f64) {
+        let dt = dt.abs();
+        for (integral, f) in self.integral.iter_mut().zip(Screen::fractions()) {
+            let u = probe::interpolate(field, self.screen.point(f), size, discretization);
+            *integral += u.powi(2) * dt;
+        }
+        self.duration += dt;
+    }
+
+    pub fn reset(&mut self) {
+        self.integral.fill(0.0);
+        self.duration = 0.0;
+    }
+
+    /// Intensity collected so far, compared to the far field of `slits` for
+    /// waves of `wavelength` if given.
+    pub fn profile(&self, slits: Option<(&scene::Slits, f64)>) -> Profile {
+        let screen = self.screen;
+        let duration = self.duration.max(f64::MIN_POSITIVE);
+        let intensity: Vec<f64> = self.integral.iter().map(|i| i / duration).collect();
+
+        let prediction = slits.map(|(slits, wavelength)| {
+            let measured = intensity.iter().fold(0.0, |a: f64, &b| a.max(b));
+            Screen::fractions()
+                .map(|f| measured * slits.fraunhofer(screen.point(f), wavelength))
+                .collect()
+        });
+
+        Profile {
+            screen,
+            distance: Screen::fractions().map(|f| f * screen.length()).collect(),
+            integral: self.integral.clone(),
+            intensity,
+            prediction,
+        }
+    }
+}
+
+impl FromStr for Screen {
+    type Err = anyhow::Error;
+
+    /// Ends in m given as `x1,y1,x2,y2`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let coordinates = s
+            .split(',')
+            .map(|c| c.trim().parse())
+            .collect::<Result<Vec<f64>, _>>()?;
+        match coordinates[..] {
+            [x1, y1, x2, y2] => Ok(Self {
+                start: (x1, y1),
+                end: (x2, y2),
+            }),
+            _ => anyhow::bail!("Expected the ends of a line `x1,y1,x2,y2`, got `{}`", s),
+        }
+    }
+}
+
+impl Profile {
+    /// Write the profile as CSV, one row per sample.
+    pub fn write_csv(&self, mut writer: impl Write) -> io::Result<()> {
+        writeln!(writer, "s,integral,mean_intensity,fraunhofer")?;
+        for (i, (s, intensity)) in self.distance.iter().zip(&self.intensity).enumerate() {
+            write!(writer, "{},{:e},{:e},", s, self.integral[i], intensity)?;
+            match &self.prediction {
+                Some(prediction) => writeln!(writer, "{:e}", prediction[i])?,
+                None => writeln!(writer)?,
+            }
+        }
+        writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile() {
+        let screen: Screen = "9, 0, 9, 10".parse().unwrap();
+        assert_eq!(screen.length(), 10.0);
+        assert!("1,2,3".parse::<Screen>().is_err());
+
+        // intensity growing downwards for 2 s, then nothing for 2 s
+        let n = 10;
+        let field: Vec<f64> = (0..n * n).map(|i| ((i / n) as f64).sqrt()).collect();
+        let mut detector = Detector::new(screen);
+        detector.record(&field, (10.0, 10.0), n as u32, 2.0);
+        detector.record(&vec![0.0; n * n], (10.0, 10.0), n as u32, -2.0);
+        let slits = scene::Scene::DoubleSlit.slits((10.0, 10.0)).unwrap();
+        let profile = detector.profile(Some((&slits, 1.0)));
+
+        assert_eq!(profile.distance.len(), Screen::SAMPLES);
+        assert_eq!(profile.distance[Screen::SAMPLES - 1], 10.0);
+        assert_eq!(profile.intensity[0], 0.0);
+        // earlier hits are not forgotten
+        assert!((profile.integral[Screen::SAMPLES - 1] - 18.0).abs() < 1e-9);
+        assert!((profile.intensity[Screen::SAMPLES - 1] - 4.5).abs() < 1e-9);
+
+        // the central maximum of the prediction lies level with the slits
+        let prediction = profile.prediction.unwrap();
+        let center = (0..Screen::SAMPLES)
+            .max_by(|&a, &b| prediction[a].total_cmp(&prediction[b]))
+            .unwrap();
+        assert!((profile.distance[center] - slits.center.1).abs() < 0.05);
+        assert!(prediction[center] <= 4.5 + 1e-9);
+
+        detector.reset();
+        assert_eq!(detector.profile(None).integral[Screen::SAMPLES - 1], 0.0);
+    }
+}
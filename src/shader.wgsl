@@ -16,18 +16,258 @@ fn vs_main(
 ) -> VertexOutput {
     var out: VertexOutput;
     out.tex_coords = model.tex_coords;
-    out.clip_position = vec4<f32>(model.position, 0.0, 1.0);
+    out.clip_position = vec4<f32>(model.position * settings.zoom + settings.offset, 0.0, 1.0);
     return out;
 }
 
 // Fragment shader
 
+// visualization modes, matching `vis::Mode`
+const MODE_FIELD: u32 = 0u;
+const MODE_AMPLITUDE: u32 = 1u;
+const MODE_INTENSITY: u32 = 2u;
+const MODE_AVERAGED_INTENSITY: u32 = 3u;
+const MODE_DECIBEL: u32 = 4u;
+const MODE_PHASE: u32 = 5u;
+const MODE_RMS: u32 = 6u;
+const MODE_ENVELOPE: u32 = 7u;
+const MODE_PEAK_TIME: u32 = 8u;
+const MODE_HARMONIC_AMPLITUDE: u32 = 9u;
+
+const PI: f32 = 3.14159265358979;
+
+struct Settings {
+    // absolute value mapped to the ends of the colormap
+    clamp: f32,
+    mode: u32,
+    // dynamic range in dB shown in the decibel mode
+    db_range: f32,
+    // view transform of the quad
+    zoom: f32,
+    offset: vec2<f32>,
+    // show cells as blocks instead of interpolating between them
+    nearest: u32,
+    // water height at the ends of the colormap in the ripple tank, in m
+    height: f32,
+    // physical size of the simulation in m
+    size: vec2<f32>,
+    // effective distance over which refracted rays spread in the ripple tank, in m
+    focus: f32,
+    // draw walls and tint media with a different wave speed
+    show_medium: u32,
+    // number of bands the colormap is split into by contour lines, 0 hides them
+    contours: u32,
+}
+
 @group(0) @binding(0)
-var t_diffuse: texture_2d<f32>;
+var t_field: texture_2d<f32>;
 @group(0) @binding(1)
-var s_diffuse: sampler;
+var<uniform> settings: Settings;
+@group(0) @binding(2)
+var t_colormap: texture_2d<f32>;
+@group(0) @binding(3)
+var s_colormap: sampler;
+// wave speed relative to free space, zero inside walls
+@group(0) @binding(4)
+var t_medium: texture_2d<f32>;
+
+const WALL_COLOR: vec3<f32> = vec3<f32>(0.25, 0.25, 0.25);
+const MEDIUM_TINT: vec3<f32> = vec3<f32>(0.0, 0.8, 0.4);
+
+fn load(coords: vec2<i32>) -> f32 {
+    let dims = vec2<i32>(textureDimensions(t_field));
+    return textureLoad(t_field, clamp(coords, vec2<i32>(0), dims - 1), 0).r;
+}
+
+// bilinear interpolation of the field, float textures are not filterable
+fn field(tex_coords: vec2<f32>) -> f32 {
+    if settings.nearest != 0u {
+        return load(vec2<i32>(floor(tex_coords * vec2<f32>(textureDimensions(t_field)))));
+    }
+    let pos = tex_coords * vec2<f32>(textureDimensions(t_field)) - 0.5;
+    let base = vec2<i32>(floor(pos));
+    let f = fract(pos);
+    let top = mix(load(base), load(base + vec2<i32>(1, 0)), f.x);
+    let bottom = mix(load(base + vec2<i32>(0, 1)), load(base + vec2<i32>(1, 1)), f.x);
+    return mix(top, bottom, f.y);
+}
+
+// look up t in [0, 1] in the colormap, hitting the centers of the first and last texel
+fn colormap(t: f32) -> vec4<f32> {
+    let size = f32(textureDimensions(t_colormap).x);
+    let x = (t * (size - 1.0) + 0.5) / size;
+    return textureSample(t_colormap, s_colormap, vec2<f32>(x, 0.5));
+}
+
+// map the value of the field to [0, 1] according to the mode
+fn scale(u: f32) -> f32 {
+    switch settings.mode {
+        case MODE_AMPLITUDE: {
+            return abs(u) / settings.clamp;
+        }
+        case MODE_INTENSITY: {
+            return u * u / settings.clamp;
+        }
+        case MODE_AVERAGED_INTENSITY, MODE_RMS, MODE_ENVELOPE, MODE_PEAK_TIME,
+            MODE_HARMONIC_AMPLITUDE: {
+            // the field already holds the accumulated statistic
+            return u / settings.clamp;
+        }
+        case MODE_DECIBEL: {
+            let db = 20.0 * log(max(abs(u), 1e-30) / settings.clamp) / log(10.0);
+            return 1.0 + db / settings.db_range;
+        }
+        case MODE_PHASE: {
+            // the field holds the phase in [-pi, pi]
+            return 0.5 + 0.5 * u / PI;
+        }
+        default: {
+            return 0.5 + 0.5 * u / settings.clamp;
+        }
+    }
+}
+
+fn wave_speed(tex_coords: vec2<f32>) -> f32 {
+    let dims = vec2<i32>(textureDimensions(t_medium));
+    let coords = clamp(vec2<i32>(floor(tex_coords * vec2<f32>(dims))), vec2<i32>(0), dims - 1);
+    return textureLoad(t_medium, coords, 0).r;
+}
+
+// draw walls opaque over `color` and tint it where the wave speed differs from free space
+fn with_medium(color: vec4<f32>, tex_coords: vec2<f32>) -> vec4<f32> {
+    if settings.show_medium == 0u {
+        return color;
+    }
+    let speed = wave_speed(tex_coords);
+    if speed == 0.0 {
+        return vec4<f32>(WALL_COLOR, 1.0);
+    }
+    let alpha = clamp(abs(1.0 - speed), 0.0, 0.5);
+    return vec4<f32>(mix(color.rgb, MEDIUM_TINT, alpha), color.a);
+}
+
+// darken `color` where `t` crosses a contour level, about one pixel wide
+fn with_contours(color: vec4<f32>, t: f32) -> vec4<f32> {
+    let x = t * f32(settings.contours);
+    // distance to the closest level in pixels
+    let distance = abs(fract(x + 0.5) - 0.5) / max(fwidth(x), 1e-6);
+    // saturated regions lie on a level everywhere
+    let visible = settings.contours > 0u && t > 0.0 && t < 1.0;
+    let line = select(0.0, 0.7, visible) * (1.0 - clamp(distance - 0.5, 0.0, 1.0));
+    return vec4<f32>(mix(color.rgb, vec3<f32>(0.0), line), color.a);
+}
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    let t = clamp(scale(field(in.tex_coords)), 0.0, 1.0);
+    return with_medium(with_contours(colormap(t), t), in.tex_coords);
+}
+
+// displacement of the surface in [-1, 1] for signed quantities and [0, 1] for magnitudes
+fn displacement(tex_coords: vec2<f32>) -> f32 {
+    let t = clamp(scale(field(tex_coords)), 0.0, 1.0);
+    if settings.mode == MODE_FIELD || settings.mode == MODE_PHASE {
+        return 2.0 * t - 1.0;
+    }
+    return t;
+}
+
+// Ripple tank
+
+fn water_height(tex_coords: vec2<f32>) -> f32 {
+    return displacement(tex_coords) * settings.height;
+}
+
+// Light of a point lamp refracted by the water surface onto a screen below.
+//
+// Rays bend towards the normal of the surface, a ray through x lands at
+// x - focus * grad h(x). The brightness on the screen is the inverse of the
+// area change 1 / det(I - focus * hessian h), so crests focus the light and
+// caustics appear where the determinant vanishes.
+@fragment
+fn fs_ripple(in: VertexOutput) -> @location(0) vec4<f32> {
+    let dims = vec2<f32>(textureDimensions(t_field));
+    let step = 1.0 / dims;
+    let dx = vec2<f32>(step.x, 0.0);
+    let dy = vec2<f32>(0.0, step.y);
+    let cell = settings.size * step;
+
+    // find the point on the surface the light reaching this pixel came from
+    let grad = vec2<f32>(
+        water_height(in.tex_coords + dx) - water_height(in.tex_coords - dx),
+        water_height(in.tex_coords + dy) - water_height(in.tex_coords - dy),
+    ) / (2.0 * cell);
+    let p = in.tex_coords + settings.focus * grad / settings.size;
+
+    let h = water_height(p);
+    let h_xx = (water_height(p + dx) - 2.0 * h + water_height(p - dx)) / (cell.x * cell.x);
+    let h_yy = (water_height(p + dy) - 2.0 * h + water_height(p - dy)) / (cell.y * cell.y);
+    let h_xy = (water_height(p + dx + dy) - water_height(p + dx - dy)
+        - water_height(p - dx + dy) + water_height(p - dx - dy)) / (4.0 * cell.x * cell.y);
+    let det = (1.0 - settings.focus * h_xx) * (1.0 - settings.focus * h_yy)
+        - settings.focus * settings.focus * h_xy * h_xy;
+
+    // a flat surface is shown in half the brightness, leaving room for the bright bands
+    let brightness = clamp(0.5 / max(abs(det), 1e-3), 0.0, 1.0);
+    return with_medium(vec4<f32>(brightness * vec3<f32>(1.0, 0.97, 0.9), 1.0), in.tex_coords);
+}
+
+// Height field surface
+
+struct Camera {
+    view_proj: mat4x4<f32>,
+    // half extent of the surface in the horizontal plane
+    extent: vec2<f32>,
+    // distance between neighboring vertices in texture coordinates
+    texel: vec2<f32>,
+    // height of a value at the end of the colormap
+    height: f32,
+}
+
+@group(1) @binding(0)
+var<uniform> camera: Camera;
+
+struct SurfaceOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) t: f32,
+    @location(1) normal: vec3<f32>,
+    @location(2) tex_coords: vec2<f32>,
+}
+
+// position of the colormap in [0, 1] at a point of the field
+fn surface_t(tex_coords: vec2<f32>) -> f32 {
+    return clamp(scale(field(tex_coords)), 0.0, 1.0);
+}
+
+fn surface_point(tex_coords: vec2<f32>) -> vec3<f32> {
+    let xz = (tex_coords - 0.5) * 2.0 * camera.extent;
+    var h = displacement(tex_coords);
+    // walls stand out of the water
+    if settings.show_medium != 0u && wave_speed(tex_coords) == 0.0 {
+        h = 1.0;
+    }
+    return vec3<f32>(xz.x, h * camera.height, xz.y);
+}
+
+@vertex
+fn vs_surface(@location(0) tex_coords: vec2<f32>) -> SurfaceOutput {
+    var out: SurfaceOutput;
+    let dx = vec2<f32>(camera.texel.x, 0.0);
+    let dz = vec2<f32>(0.0, camera.texel.y);
+    let tangent_x = surface_point(tex_coords + dx) - surface_point(tex_coords - dx);
+    let tangent_z = surface_point(tex_coords + dz) - surface_point(tex_coords - dz);
+    out.normal = cross(tangent_z, tangent_x);
+    out.t = surface_t(tex_coords);
+    out.tex_coords = tex_coords;
+    out.clip_position = camera.view_proj * vec4<f32>(surface_point(tex_coords), 1.0);
+    return out;
+}
+
+@fragment
+fn fs_surface(in: SurfaceOutput) -> @location(0) vec4<f32> {
+    let light = normalize(vec3<f32>(0.4, 1.0, 0.3));
+    // light both sides, the surface can be seen from below
+    let diffuse = abs(dot(normalize(in.normal), light));
+    let color = with_medium(with_contours(colormap(in.t), in.t), in.tex_coords);
+    return vec4<f32>(color.rgb * (0.3 + 0.7 * diffuse), color.a);
 }
@@ -1,10 +1,34 @@
+use crate::{flux, probe, scene, screen, stats};
+
 pub struct Simulation {
     size: (f64, f64),
     discretization: u32,
     u_n: Vec<f64>,
     u_nm1: Vec<f64>,
-    c: f64,
+    params: Parameters,
     t: f64,
+    /// Whether the leapfrog scheme currently runs backwards in time
+    reversed: bool,
+    /// `u_n` and `u_nm1` at `t = 0`, used to reset the simulation
+    initial: (Vec<f64>, Vec<f64>),
+    statistics: stats::Statistics,
+    medium: scene::Medium,
+    probes: Vec<probe::Probe>,
+    monitors: Vec<flux::Monitor>,
+    detector: Option<screen::Detector>,
+}
+
+/// Physical parameters that can be changed while the simulation is running.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Parameters {
+    /// Speed of wave in m/s
+    pub c: f64,
+    /// Damping coefficient in 1/s
+    pub damping: f64,
+    /// Angular frequency of the source in rad/s
+    pub omega: f64,
+    /// Amplitude of the source
+    pub amplitude: f64,
 }
 
 impl Simulation {
@@ -17,10 +41,34 @@ impl Simulation {
         Self {
             size,
             discretization: args.discretization,
+            initial: (u_n.clone(), u_nm1.clone()),
             u_n,
             u_nm1,
-            c: args.c,
+            params: Parameters {
+                c: args.c,
+                damping: args.damping,
+                omega: args.omega,
+                amplitude: args.amplitude,
+            },
             t: 0.0,
+            reversed: false,
+            statistics: stats::Statistics::new(
+                (args.discretization * args.discretization) as usize,
+                args.average_time,
+            ),
+            medium: args.scene.medium(size, args.discretization),
+            probes: args.probes.clone(),
+            monitors: args
+                .flux_lines
+                .iter()
+                .chain(&args.flux_boxes)
+                .map(|monitor| {
+                    let mut monitor = monitor.clone();
+                    monitor.discretize(size, args.discretization);
+                    monitor
+                })
+                .collect(),
+            detector: args.screen.map(screen::Detector::new),
         }
     }
 
@@ -32,29 +80,182 @@ impl Simulation {
     }
 
     pub fn step(&mut self, dt: f64) -> &Vec<f64> {
-        let c = self.c;
+        let Parameters {
+            c,
+            damping,
+            omega,
+            amplitude,
+        } = self.params;
+        // damping term discretized with central differences
+        let gamma = damping * dt / 2.0;
         let mut u_np1 = vec![0.0; (self.discretization * self.discretization) as usize];
 
-        for i in 0..self.u_n.len() {
+        for (i, u) in u_np1.iter_mut().enumerate() {
+            if self.medium.walls[i] {
+                continue;
+            }
             let (left, right, top, bottom) = self.get_star(i);
             let uxx = (left - 2.0 * self.u_n[i] + right)
                 / (self.size.0 / self.discretization as f64).powi(2);
             let uyy = (top - 2.0 * self.u_n[i] + bottom)
                 / (self.size.1 / self.discretization as f64).powi(2);
             let laplacian = uxx + uyy;
-            u_np1[i] = 2.0 * self.u_n[i] - self.u_nm1[i] + c.powi(2) * dt.powi(2) * laplacian;
+            let c = c / self.medium.index[i];
+            *u = (2.0 * self.u_n[i] - (1.0 - gamma) * self.u_nm1[i]
+                + c.powi(2) * dt.powi(2) * laplacian)
+                / (1.0 + gamma);
         }
 
+        // the scheme is symmetric in time, running backwards only changes the
+        // direction the clock is advanced in
+        self.t += if self.reversed { -dt } else { dt };
+
+        // the source is evaluated at the time of the new level, so that a
+        // reversed run drives it with exactly the values of the forward run
         let center = self.discretization as usize * (self.discretization as usize / 2)
             + self.discretization as usize / 2;
-        u_np1[center] = (self.t * 5.0).sin();
+        u_np1[center] = amplitude * (self.t * omega).sin();
 
         self.u_nm1 = self.u_n.clone();
         self.u_n = u_np1;
-        self.t += dt;
+
+        self.statistics.accumulate(&self.u_n, self.t, dt, omega);
+        for probe in &mut self.probes {
+            probe.record(self.t, &self.u_n, self.size, self.discretization);
+        }
+        let mut monitors = std::mem::take(&mut self.monitors);
+        for monitor in &mut monitors {
+            monitor.record(|i| self.cell_flux(i, dt), dt);
+        }
+        self.monitors = monitors;
+        if let Some(detector) = &mut self.detector {
+            detector.record(&self.u_n, self.size, self.discretization, dt);
+        }
+
+        &self.u_n
+    }
+
+    /// Reverse the direction of time.
+    ///
+    /// Swapping `u_n` and `u_nm1` turns the leapfrog scheme around, so that
+    /// further steps retrace the past of the simulation.
+    pub fn reverse(&mut self, dt: f64) {
+        std::mem::swap(&mut self.u_n, &mut self.u_nm1);
+        // `u_n` now holds the previous time level
+        self.t -= if self.reversed { -dt } else { dt };
+        self.reversed = !self.reversed;
+    }
+
+    pub fn parameters(&self) -> Parameters {
+        self.params
+    }
+
+    pub fn set_parameters(&mut self, params: Parameters) {
+        self.params = params;
+    }
+
+    /// Courant number of the scheme for the time step `dt`.
+    ///
+    /// The simulation is only stable for values up to 1, the fastest medium
+    /// limits the time step.
+    pub fn courant(&self, dt: f64) -> f64 {
+        let dx = self.size.0 / self.discretization as f64;
+        let dy = self.size.1 / self.discretization as f64;
+        let min_index = self
+            .medium
+            .index
+            .iter()
+            .fold(f64::INFINITY, |a, &b| a.min(b));
+        self.params.c / min_index * dt * (dx.powi(-2) + dy.powi(-2)).sqrt()
+    }
+
+    pub fn medium(&self) -> &scene::Medium {
+        &self.medium
+    }
+
+    /// Start recording the field at `position` in m.
+    pub fn add_probe(&mut self, position: (f64, f64)) {
+        self.probes.push(probe::Probe::new(position));
+    }
+
+    /// Intensity collected on the screen, if there is one.
+    pub fn detector(&self) -> Option<&screen::Detector> {
+        self.detector.as_ref()
+    }
+
+    pub fn probes(&self) -> &[probe::Probe] {
+        &self.probes
+    }
+
+    pub fn is_reversed(&self) -> bool {
+        self.reversed
+    }
+
+    /// Reset the simulation to its initial state at `t = 0`.
+    pub fn reset(&mut self) {
+        self.u_n.clone_from(&self.initial.0);
+        self.u_nm1.clone_from(&self.initial.1);
+        self.t = 0.0;
+        self.reversed = false;
+        self.statistics.reset();
+        for probe in &mut self.probes {
+            probe.samples.clear();
+        }
+        for monitor in &mut self.monitors {
+            monitor.reset();
+        }
+        if let Some(detector) = &mut self.detector {
+            detector.reset();
+        }
+    }
+
+    pub fn field(&self) -> &Vec<f64> {
         &self.u_n
     }
 
+    pub fn statistics(&self) -> &stats::Statistics {
+        &self.statistics
+    }
+
+    pub fn size(&self) -> (f64, f64) {
+        self.size
+    }
+
+    pub fn discretization(&self) -> u32 {
+        self.discretization
+    }
+
+    /// Energy flux `-u_t ∇u` of every cell, zero inside walls.
+    ///
+    /// With the energy density `(u_t^2 / c^2 + |∇u|^2) / 2`, this is the flux
+    /// conserved by the wave equation `u_tt = c^2 ∇^2 u` even across media.
+    pub fn flux(&self, dt: f64) -> Vec<(f64, f64)> {
+        (0..self.u_n.len()).map(|i| self.cell_flux(i, dt)).collect()
+    }
+
+    fn cell_flux(&self, i: usize, dt: f64) -> (f64, f64) {
+        if self.medium.walls[i] {
+            return (0.0, 0.0);
+        }
+        let dx = self.size.0 / self.discretization as f64;
+        let dy = self.size.1 / self.discretization as f64;
+        // `u_nm1` lies in the future while running backwards
+        let dt = if self.reversed { -dt } else { dt };
+        let (left, right, top, bottom) = self.get_star(i);
+        let u_t = (self.u_n[i] - self.u_nm1[i]) / dt;
+        let u_x = (right - left) / (2.0 * dx);
+        let u_y = (bottom - top) / (2.0 * dy);
+        (-u_t * u_x, -u_t * u_y)
+    }
+
+    /// Mean power through every flux monitor since the last reset.
+    pub fn flux_reports(&self) -> Vec<flux::Report> {
+        self.monitors
+            .iter()
+            .map(|monitor| monitor.report())
+            .collect()
+    }
+
     pub fn energy(&self) -> f64 {
         self.u_n.iter().map(|x| x.abs().powi(2)).sum()
     }
@@ -63,6 +264,7 @@ impl Simulation {
         self.t
     }
 
+    #[allow(dead_code)]
     fn init_value_gauss(size: (f64, f64), disc: u32) -> Vec<f64> {
         let mu = 0.0;
         let sigma = 5.0;
@@ -82,7 +284,7 @@ impl Simulation {
     }
 
     fn get_star(&self, n: usize) -> (f64, f64, f64, f64) {
-        let left = if n as u32 % self.discretization == 0 {
+        let left = if (n as u32).is_multiple_of(self.discretization) {
             0.0
         } else {
             self.u_n[n - 1]
@@ -113,6 +315,7 @@ impl Simulation {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use clap::Parser;
 
     #[test]
     fn test_gauss() {
@@ -127,8 +330,20 @@ mod tests {
             discretization: 3,
             u_n: vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0],
             u_nm1: vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0],
-            c: 1.0,
+            params: Parameters {
+                c: 1.0,
+                damping: 0.0,
+                omega: 5.0,
+                amplitude: 1.0,
+            },
             t: 0.0,
+            reversed: false,
+            initial: (vec![0.0; 9], vec![0.0; 9]),
+            statistics: stats::Statistics::new(9, 1.0),
+            medium: scene::Medium::uniform(9),
+            probes: vec![],
+            monitors: vec![],
+            detector: None,
         };
 
         assert_eq!(sim.get_star(0), (0.0, 1.0, 0.0, 3.0));
@@ -141,4 +356,121 @@ mod tests {
         assert_eq!(sim.get_star(7), (6.0, 8.0, 4.0, 0.0));
         assert_eq!(sim.get_star(8), (7.0, 0.0, 5.0, 0.0));
     }
+
+    #[test]
+    fn test_reverse() {
+        let args = crate::Args::parse_from(["wave-simmers", "--discretization", "11"]);
+        let mut sim = Simulation::new(&args);
+        let dt = 1e-2;
+
+        sim.multi_step(20, dt);
+        assert!(sim.energy() > 0.0);
+
+        sim.reverse(dt);
+        assert!(sim.is_reversed());
+        sim.multi_step(19, dt);
+
+        // back at the initial, empty state
+        assert!(sim.time().abs() < 1e-12);
+        assert!(sim.energy() < 1e-20);
+
+        sim.reverse(dt);
+        assert!(!sim.is_reversed());
+        assert!((sim.time() - dt).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_reset() {
+        let args = crate::Args::parse_from(["wave-simmers", "--discretization", "11"]);
+        let mut sim = Simulation::new(&args);
+
+        sim.multi_step(10, 1e-2);
+        sim.reverse(1e-2);
+        sim.reset();
+
+        assert_eq!(sim.time(), 0.0);
+        assert!(!sim.is_reversed());
+        assert_eq!(sim.energy(), 0.0);
+    }
+
+    #[test]
+    fn test_walls() {
+        let args = crate::Args::parse_from([
+            "wave-simmers",
+            "--discretization",
+            "100",
+            "--scene",
+            "double-slit",
+        ]);
+        let mut sim = Simulation::new(&args);
+        sim.multi_step(100, 1e-2);
+
+        for (u, &wall) in sim.field().iter().zip(&sim.medium().walls) {
+            if wall {
+                assert_eq!(*u, 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_flux() {
+        let args = crate::Args::parse_from(["wave-simmers", "--discretization", "41"]);
+        let mut sim = Simulation::new(&args);
+        sim.multi_step(500, 1e-2);
+
+        // energy flows away from the source in the center
+        let flux = sim.flux(1e-2);
+        let (right, _) = flux[20 * 41 + 30];
+        let (left, _) = flux[20 * 41 + 10];
+        let (_, down) = flux[30 * 41 + 20];
+        let (_, up) = flux[10 * 41 + 20];
+        assert!(right > 0.0 && left < 0.0 && down > 0.0 && up < 0.0);
+    }
+
+    #[test]
+    fn test_flux_monitors() {
+        let args = crate::Args::parse_from([
+            "wave-simmers",
+            "--discretization",
+            "41",
+            "--flux-box",
+            "4,4,6,6",
+            "--flux-line",
+            "7,3,7,7",
+        ]);
+        let mut sim = Simulation::new(&args);
+        sim.multi_step(400, 1e-2);
+
+        // lines come first, the source inside the box radiates outwards
+        // and part of it crosses the line
+        let reports = sim.flux_reports();
+        let (line, enclosure) = (reports[0], reports[1]);
+        assert!(enclosure.forward > 0.0);
+        assert!(enclosure.reflection().unwrap() < 0.01);
+        assert!(line.reflection().unwrap() < 0.01);
+        assert!(line.transmission(&enclosure).unwrap() < 0.5);
+
+        sim.reset();
+        assert_eq!(sim.flux_reports()[1].forward, 0.0);
+    }
+
+    #[test]
+    fn test_probes() {
+        let args =
+            crate::Args::parse_from(["wave-simmers", "--discretization", "11", "--probe", "5,5"]);
+        let mut sim = Simulation::new(&args);
+        sim.add_probe((2.0, 5.0));
+        sim.multi_step(10, 1e-2);
+
+        let probes = sim.probes();
+        assert_eq!(probes.len(), 2);
+        assert_eq!(probes[0].samples.len(), 10);
+        // the probe at the source sees the source term
+        let (t, u) = probes[0].samples[9];
+        assert!((t - 0.1).abs() < 1e-12);
+        assert!((u - (t * args.omega).sin()).abs() < 1e-12);
+
+        sim.reset();
+        assert!(sim.probes()[1].samples.is_empty());
+    }
 }
@@ -0,0 +1,236 @@
+use rustfft::num_complex::Complex;
+use std::io::{self, Write};
+
+/// Window applied to a time series before its transform, trading the width
+/// of peaks for the leakage into neighboring frequencies.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Window {
+    /// No window, narrowest peaks and most leakage
+    Rectangular,
+    #[default]
+    Hann,
+    Hamming,
+    /// Least leakage, widest peaks
+    Blackman,
+}
+
+impl Window {
+    /// Weight of sample `i` of `n`.
+    fn weight(self, i: usize, n: usize) -> f64 {
+        if n < 2 {
+            return 1.0;
+        }
+        let x = 2.0 * std::f64::consts::PI * i as f64 / (n - 1) as f64;
+        match self {
+            Window::Rectangular => 1.0,
+            Window::Hann => 0.5 - 0.5 * x.cos(),
+            Window::Hamming => 0.54 - 0.46 * x.cos(),
+            Window::Blackman => 0.42 - 0.5 * x.cos() + 0.08 * (2.0 * x).cos(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Settings {
+    pub window: Window,
+    /// Minimum length of the zero padded series relative to the recorded one,
+    /// interpolates the spectrum between its bins
+    pub zero_padding: usize,
+}
+
+/// One sided spectrum of a real time series.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Spectrum {
+    /// Frequency of each bin in Hz
+    pub frequencies: Vec<f64>,
+    /// Amplitude of each bin, a sine of amplitude `A` on a bin peaks at `A`
+    pub magnitude: Vec<f64>,
+    /// Phase of each bin in `[-pi, pi]` relative to a cosine at the first sample
+    pub phase: Vec<f64>,
+}
+
+impl Spectrum {
+    /// Fewest samples a spectrum is taken of, the tapering windows weigh
+    /// both samples of shorter series down to zero
+    pub const MIN_SAMPLES: usize = 4;
+
+    /// Spectrum of `samples` taken every `dt` seconds, zero padded to a power
+    /// of two.
+    pub fn new(samples: &[f64], dt: f64, settings: &Settings) -> Self {
+        let n = samples.len();
+        let padded = (n * settings.zero_padding.max(1)).next_power_of_two();
+        let window_sum: f64 = (0..n).map(|i| settings.window.weight(i, n)).sum();
+
+        let mut buffer: Vec<Complex<f64>> = samples
+            .iter()
+            .enumerate()
+            .map(|(i, &u)| Complex::new(u * settings.window.weight(i, n), 0.0))
+            .chain(std::iter::repeat(Complex::new(0.0, 0.0)))
+            .take(padded)
+            .collect();
+        rustfft::FftPlanner::new()
+            .plan_fft_forward(padded)
+            .process(&mut buffer);
+
+        let bins = padded / 2 + 1;
+        let frequencies = (0..bins).map(|k| k as f64 / (padded as f64 * dt)).collect();
+        let magnitude = buffer[..bins]
+            .iter()
+            .enumerate()
+            .map(|(k, x)| {
+                // the negative frequencies fold onto the positive ones
+                let one_sided = if k == 0 || 2 * k == padded { 1.0 } else { 2.0 };
+                one_sided * x.norm() / window_sum
+            })
+            .collect();
+        let phase = buffer[..bins].iter().map(|x| x.arg()).collect();
+
+        Self {
+            frequencies,
+            magnitude,
+            phase,
+        }
+    }
+
+    /// Spectrum of the `(t, u)` samples of a probe since the last change of
+    /// the step, as reversing the run or changing `dt` breaks the even
+    /// spacing. `None` for fewer than [`Spectrum::MIN_SAMPLES`] samples.
+    pub fn of_samples(samples: &[(f64, f64)], settings: &Settings) -> Option<Self> {
+        let [.., (t0, _), (t1, _)] = samples else {
+            return None;
+        };
+        // negative while the run is reversed
+        let step = t1 - t0;
+        if step == 0.0 {
+            return None;
+        }
+        let start = samples
+            .windows(2)
+            .rposition(|w| ((w[1].0 - w[0].0) - step).abs() > 1e-6 * step.abs())
+            .map_or(0, |i| i + 1);
+        if samples.len() - start < Self::MIN_SAMPLES {
+            return None;
+        }
+        let u: Vec<f64> = samples[start..].iter().map(|&(_, u)| u).collect();
+        Some(Self::new(&u, step.abs(), settings))
+    }
+
+    /// Frequency in Hz of the strongest peak above zero, refined by fitting a
+    /// parabola through the neighboring bins.
+    pub fn dominant_frequency(&self) -> Option<f64> {
+        let (k, _) = self
+            .magnitude
+            .iter()
+            .enumerate()
+            .skip(1)
+            .max_by(|a, b| a.1.total_cmp(b.1))?;
+        let bin_width = self.frequencies[1] - self.frequencies[0];
+        let offset = match (self.magnitude.get(k - 1), self.magnitude.get(k + 1)) {
+            (Some(&left), Some(&right)) if k > 1 => {
+                let center = self.magnitude[k];
+                let curvature = left - 2.0 * center + right;
+                if curvature < 0.0 {
+                    0.5 * (left - right) / curvature
+                } else {
+                    0.0
+                }
+            }
+            _ => 0.0,
+        };
+        Some(self.frequencies[k] + offset * bin_width)
+    }
+}
+
+/// Write the spectra of all probes as CSV, one row per bin.
+pub fn write_csv(spectra: &[Option<Spectrum>], mut writer: impl Write) -> io::Result<()> {
+    writeln!(writer, "probe,f,magnitude,phase")?;
+    for (i, spectrum) in spectra.iter().enumerate() {
+        let Some(spectrum) = spectrum else {
+            continue;
+        };
+        for ((f, magnitude), phase) in spectrum
+            .frequencies
+            .iter()
+            .zip(&spectrum.magnitude)
+            .zip(&spectrum.phase)
+        {
+            writeln!(writer, "{},{:e},{:e},{:e}", i + 1, f, magnitude, phase)?;
+        }
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sine() {
+        let settings = Settings {
+            window: Window::Rectangular,
+            zero_padding: 1,
+        };
+        // 8 periods of 2 Hz in 256 samples lie exactly on a bin
+        let dt = 1.0 / 64.0;
+        let samples: Vec<f64> = (0..256)
+            .map(|i| 0.5 * (2.0 * std::f64::consts::PI * 2.0 * i as f64 * dt).cos())
+            .collect();
+        let spectrum = Spectrum::new(&samples, dt, &settings);
+
+        assert_eq!(spectrum.frequencies.len(), 129);
+        assert_eq!(spectrum.frequencies[8], 2.0);
+        assert!((spectrum.magnitude[8] - 0.5).abs() < 1e-12);
+        assert!(spectrum.phase[8].abs() < 1e-12);
+        assert!(spectrum.magnitude[7] < 1e-12);
+        assert_eq!(spectrum.dominant_frequency(), Some(2.0));
+    }
+
+    #[test]
+    fn test_dominant_frequency() {
+        let settings = Settings {
+            window: Window::Hann,
+            zero_padding: 4,
+        };
+        // between bins, found by the padding and the parabola
+        let samples: Vec<(f64, f64)> = (0..300)
+            .map(|i| {
+                let t = i as f64 * 0.01;
+                (t, (2.0 * std::f64::consts::PI * 3.3 * t).sin())
+            })
+            .collect();
+        let spectrum = Spectrum::of_samples(&samples, &settings).unwrap();
+        assert_eq!(spectrum.frequencies.len(), 1024 + 1);
+        assert!((spectrum.dominant_frequency().unwrap() - 3.3).abs() < 0.01);
+
+        assert!(Spectrum::of_samples(&samples[..1], &settings).is_none());
+    }
+
+    #[test]
+    fn test_reversed_samples() {
+        let settings = Settings {
+            window: Window::Rectangular,
+            zero_padding: 1,
+        };
+        // 2 Hz sampled at 64 Hz for 4 s, then reversed for 2 s
+        let dt = 1.0 / 64.0;
+        let u = |t: f64| (2.0 * std::f64::consts::PI * 2.0 * t).cos();
+        let forward = (0..256).map(|i| i as f64 * dt);
+        let backward = (1..=128).map(|i| (256 - i) as f64 * dt);
+        let samples: Vec<(f64, f64)> = forward.chain(backward).map(|t| (t, u(t))).collect();
+
+        let spectrum = Spectrum::of_samples(&samples, &settings).unwrap();
+        // only the reversed stretch, at the spacing of the steps
+        assert_eq!(spectrum.frequencies.len(), 64 + 1);
+        assert_eq!(spectrum.frequencies[64], 32.0);
+        assert_eq!(spectrum.dominant_frequency(), Some(2.0));
+
+        // too few samples since the reversal, for any window
+        let samples = &samples[..256 + 2];
+        assert!(Spectrum::of_samples(samples, &settings).is_none());
+        let hann = Settings {
+            window: Window::Hann,
+            ..settings
+        };
+        assert!(Spectrum::of_samples(samples, &hann).is_none());
+    }
+}
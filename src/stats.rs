@@ -0,0 +1,184 @@
+use rustfft::num_complex::Complex;
+use std::io::{self, Write};
+
+/// Statistics of every cell of the field, accumulated alongside the steps.
+///
+/// Steps older than the window are forgotten exponentially, an infinite
+/// window accumulates everything since the last reset.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Statistics {
+    /// Time constant of the forgetting in seconds
+    window: f64,
+    /// Steps accumulated since the last reset
+    count: u64,
+    /// Time averaged intensity `<u^2>`
+    intensity: Vec<f64>,
+    /// Largest `|u|`, decaying with the window
+    envelope: Vec<f64>,
+    /// Time the envelope was last reached at
+    peak_time: Vec<f64>,
+    /// Complex amplitude `A` at the source frequency, `u = Re(A e^(iωt))`
+    phasor: Vec<Complex<f64>>,
+}
+
+impl Statistics {
+    pub fn new(cells: usize, window: f64) -> Self {
+        Self {
+            window,
+            count: 0,
+            intensity: vec![0.0; cells],
+            envelope: vec![0.0; cells],
+            peak_time: vec![0.0; cells],
+            phasor: vec![Complex::new(0.0, 0.0); cells],
+        }
+    }
+
+    /// Add the field at time `t`, reached by a step of `dt`, driven at the
+    /// angular frequency `omega`.
+    ///
+    /// The phasor is the discrete Fourier transform at `omega` over the
+    /// window, which should span many periods to average out the mirrored
+    /// component at `-omega`.
+    pub fn accumulate(&mut self, field: &[f64], t: f64, dt: f64, omega: f64) {
+        self.count += 1;
+        // a plain mean until the window is filled, so the first steps are
+        // not weighed down by the zeros before them
+        let weight = (dt / self.window).max(1.0 / self.count as f64).min(1.0);
+        let decay = (-dt / self.window).exp();
+        // `u e^(-iωt)` averages to `A / 2`
+        let demodulation = Complex::from_polar(2.0, -omega * t);
+
+        for (i, u) in field.iter().enumerate() {
+            self.intensity[i] += weight * (u.powi(2) - self.intensity[i]);
+            self.envelope[i] *= decay;
+            if u.abs() >= self.envelope[i] {
+                self.envelope[i] = u.abs();
+                self.peak_time[i] = t;
+            }
+            let phasor = self.phasor[i];
+            self.phasor[i] = phasor + weight * (u * demodulation - phasor);
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.count = 0;
+        self.intensity.fill(0.0);
+        self.envelope.fill(0.0);
+        self.peak_time.fill(0.0);
+        self.phasor.fill(Complex::new(0.0, 0.0));
+    }
+
+    /// Time averaged intensity `<u^2>`.
+    pub fn intensity(&self) -> &Vec<f64> {
+        &self.intensity
+    }
+
+    /// Root mean square `sqrt(<u^2>)`.
+    pub fn rms(&self) -> Vec<f64> {
+        self.intensity.iter().map(|i| i.sqrt()).collect()
+    }
+
+    /// Largest magnitude `|u|` within the window.
+    pub fn envelope(&self) -> &Vec<f64> {
+        &self.envelope
+    }
+
+    /// Time in seconds the envelope was reached at.
+    pub fn peak_time(&self) -> &Vec<f64> {
+        &self.peak_time
+    }
+
+    /// Amplitude `|A|` of the time harmonic field at the source frequency.
+    pub fn harmonic_amplitude(&self) -> Vec<f64> {
+        self.phasor.iter().map(|a| a.norm()).collect()
+    }
+
+    /// Phase `φ` in `[-pi, pi]` of the time harmonic field `|A| cos(ωt + φ)`.
+    pub fn phase(&self) -> Vec<f64> {
+        self.phasor.iter().map(|a| a.arg()).collect()
+    }
+
+    /// Write the statistics as CSV, one row per cell of a grid with
+    /// `discretization` cells along each side of a domain of `size` in m.
+    pub fn write_csv(
+        &self,
+        size: (f64, f64),
+        discretization: u32,
+        mut writer: impl Write,
+    ) -> io::Result<()> {
+        let n = discretization as usize;
+        writeln!(
+            writer,
+            "x,y,intensity,rms,envelope,peak_time,harmonic_amplitude,phase"
+        )?;
+        for (i, intensity) in self.intensity.iter().enumerate() {
+            // cell centers, as for the probes
+            let x = ((i % n) as f64 + 0.5) / n as f64 * size.0;
+            let y = ((i / n) as f64 + 0.5) / n as f64 * size.1;
+            writeln!(
+                writer,
+                "{},{},{:e},{:e},{:e},{:e},{:e},{:e}",
+                x,
+                y,
+                intensity,
+                intensity.sqrt(),
+                self.envelope[i],
+                self.peak_time[i],
+                self.phasor[i].norm(),
+                self.phasor[i].arg()
+            )?;
+        }
+        writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accumulate() {
+        let mut stats = Statistics::new(1, f64::INFINITY);
+        let dt = 1e-3;
+        // a half period of a sine of amplitude 2, peaking at t = 0.5
+        for i in 0..1000 {
+            let t = i as f64 * dt;
+            let u = 2.0 * (std::f64::consts::PI * t).sin();
+            stats.accumulate(&[u], t, dt, 1.0);
+        }
+        assert!((stats.intensity()[0] - 2.0).abs() < 1e-9);
+        assert!((stats.rms()[0] - 2f64.sqrt()).abs() < 1e-9);
+        assert_eq!(stats.envelope()[0], 2.0);
+        assert_eq!(stats.peak_time()[0], 0.5);
+
+        stats.reset();
+        assert_eq!(stats.intensity()[0], 0.0);
+    }
+
+    #[test]
+    fn test_window() {
+        let mut stats = Statistics::new(1, 0.1);
+        stats.accumulate(&[1.0], 0.0, 0.01, 1.0);
+        for i in 1..100 {
+            stats.accumulate(&[0.0], i as f64 * 0.01, 0.01, 1.0);
+        }
+        // the pulse is forgotten after many windows
+        assert!(stats.intensity()[0] < 1e-3);
+        assert!(stats.envelope()[0] < 1e-3);
+        assert_eq!(stats.peak_time()[0], 0.0);
+    }
+
+    #[test]
+    fn test_phasor() {
+        let (omega, phi, dt) = (5.0, 0.7, 1e-3);
+        let mut stats = Statistics::new(1, f64::INFINITY);
+        // 20 full periods
+        let steps = (20.0 * 2.0 * std::f64::consts::PI / omega / dt).round() as usize;
+        for i in 0..steps {
+            let t = i as f64 * dt;
+            stats.accumulate(&[0.5 * (omega * t + phi).cos()], t, dt, omega);
+        }
+        assert!((stats.harmonic_amplitude()[0] - 0.5).abs() < 1e-3);
+        assert!((stats.phase()[0] - phi).abs() < 1e-3);
+    }
+}
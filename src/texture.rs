@@ -1,26 +1,76 @@
 pub struct Texture {
     pub texture: wgpu::Texture,
     pub view: wgpu::TextureView,
-    pub sampler: wgpu::Sampler,
 }
 
 impl Texture {
-    pub fn test_texture(
+    /// Single channel float texture holding one value of the field per texel.
+    ///
+    /// `R32Float` textures can not be filtered on all adapters, the shader
+    /// reads them with `textureLoad` instead of a sampler.
+    pub fn field_texture(device: &wgpu::Device, label: &str, dims: (u32, u32)) -> Self {
+        Self::new(
+            device,
+            label,
+            dims,
+            wgpu::TextureFormat::R32Float,
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        )
+    }
+
+    /// Lookup table of sRGB colors used for colormapping.
+    pub fn lut_texture(device: &wgpu::Device, label: &str, size: u32) -> Self {
+        Self::new(
+            device,
+            label,
+            (size, 1),
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        )
+    }
+
+    pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+    /// Depth buffer for 3D rendering into a target of `dims`.
+    pub fn depth_texture(device: &wgpu::Device, label: &str, dims: (u32, u32)) -> Self {
+        Self::new(
+            device,
+            label,
+            dims,
+            Self::DEPTH_FORMAT,
+            wgpu::TextureUsages::RENDER_ATTACHMENT,
+        )
+    }
+
+    /// Color attachment of `format` whose pixels can be read back.
+    pub fn render_target(
         device: &wgpu::Device,
-        queue: &wgpu::Queue,
         label: &str,
         dims: (u32, u32),
+        format: wgpu::TextureFormat,
     ) -> Self {
-        let data = vec![0; (dims.0 * dims.1 * 4) as usize];
-        let dimensions = (dims.0, dims.1);
+        Self::new(
+            device,
+            label,
+            dims,
+            format,
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        )
+    }
 
+    fn new(
+        device: &wgpu::Device,
+        label: &str,
+        dims: (u32, u32),
+        format: wgpu::TextureFormat,
+        usage: wgpu::TextureUsages,
+    ) -> Self {
         let size = wgpu::Extent3d {
-            width: dimensions.0,
-            height: dimensions.1,
+            width: dims.0,
+            height: dims.1,
             depth_or_array_layers: 1,
         };
 
-        let format = wgpu::TextureFormat::Rgba8Unorm;
         let texture = device.create_texture(&wgpu::TextureDescriptor {
             label: Some(label),
             size,
@@ -28,41 +78,95 @@ impl Texture {
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
             format,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            usage,
             view_formats: &[],
         });
 
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        Self { texture, view }
+    }
+
+    /// Upload `data` into the whole texture, tightly packed in rows.
+    pub fn write(&self, queue: &wgpu::Queue, data: &[u8]) {
+        let size = self.texture.size();
+        let texel_size = self.texture.format().block_copy_size(None).unwrap_or(4);
         queue.write_texture(
             wgpu::ImageCopyTexture {
                 aspect: wgpu::TextureAspect::All,
-                texture: &texture,
+                texture: &self.texture,
                 mip_level: 0,
                 origin: wgpu::Origin3d::ZERO,
             },
-            &data,
+            data,
             wgpu::ImageDataLayout {
                 offset: 0,
-                bytes_per_row: Some(dimensions.0 * 4),
-                rows_per_image: Some(dimensions.1),
+                bytes_per_row: Some(size.width * texel_size),
+                rows_per_image: Some(size.height),
             },
             size,
         );
+    }
 
-        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
-            ..Default::default()
+    /// Download the whole texture, tightly packed in rows, waiting for the
+    /// GPU to finish all submitted work.
+    pub fn read(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<Vec<u8>, wgpu::BufferAsyncError> {
+        let size = self.texture.size();
+        let texel_size = self.texture.format().block_copy_size(None).unwrap_or(4);
+        let row_size = size.width * texel_size;
+        // copies to buffers need rows aligned to 256 bytes
+        let padded_row_size = row_size.div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+            * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Readback Buffer"),
+            size: (padded_row_size * size.height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Readback Encoder"),
+        });
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                aspect: wgpu::TextureAspect::All,
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row_size),
+                    rows_per_image: Some(size.height),
+                },
+            },
+            size,
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
         });
+        device.poll(wgpu::Maintain::Wait);
+        receiver
+            .recv()
+            .expect("Buffer mapping callback was dropped")?;
 
-        Self {
-            texture,
-            view,
-            sampler,
-        }
+        let data = slice
+            .get_mapped_range()
+            .chunks(padded_row_size as usize)
+            .flat_map(|row| &row[..row_size as usize])
+            .copied()
+            .collect();
+        buffer.unmap();
+        Ok(data)
     }
 }
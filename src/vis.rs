@@ -1,4 +1,5 @@
-use crate::texture;
+use crate::{colormap, flux, gpu, gui, heightfield, hud, overlay, pacing, range, screen, texture};
+use std::time::Instant;
 use wgpu::util::DeviceExt;
 
 #[repr(C)]
@@ -53,59 +54,466 @@ const VERTICES: &[Vertex] = &[
 // make rectangle out of two triangles
 const INDICES: &[u16] = &[0, 1, 2, 1, 3, 2, /* padding */ 0];
 
-pub struct Visualizer<'window> {
+/// Errors the visualizer can not recover from.
+#[derive(Debug)]
+pub enum VisError {
+    CreateSurface(wgpu::CreateSurfaceError),
+    /// No adapter can draw to the window
+    NoAdapter,
+    RequestDevice(wgpu::RequestDeviceError),
+    /// The surface failed for a reason other than being lost, outdated or timed out
+    Surface(wgpu::SurfaceError),
+    /// Rendered pixels could not be read back from the GPU
+    Readback(wgpu::BufferAsyncError),
+    /// An image size is empty or exceeds the largest texture of the device
+    Resolution {
+        resolution: (u32, u32),
+        max: u32,
+    },
+}
+
+impl std::fmt::Display for VisError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VisError::CreateSurface(e) => {
+                write!(f, "Failed to create a surface for the window: {}", e)
+            }
+            VisError::NoAdapter => {
+                write!(f, "No graphics adapter found that can draw to the window")
+            }
+            VisError::RequestDevice(e) => write!(f, "Failed to open the graphics device: {}", e),
+            VisError::Surface(e) => write!(f, "Failed to draw to the window: {}", e),
+            VisError::Readback(e) => write!(f, "Failed to read back the image: {}", e),
+            VisError::Resolution { resolution, max } => write!(
+                f,
+                "Cannot render an image of {}x{} px, the device supports 1 to {} px per side",
+                resolution.0, resolution.1, max
+            ),
+        }
+    }
+}
+
+impl std::error::Error for VisError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            VisError::CreateSurface(e) => Some(e),
+            VisError::NoAdapter | VisError::Resolution { .. } => None,
+            VisError::RequestDevice(e) => Some(e),
+            VisError::Surface(e) => Some(e),
+            VisError::Readback(e) => Some(e),
+        }
+    }
+}
+
+/// The window frames are presented in, with the control panel on top.
+struct Presenter<'window> {
+    window: &'window winit::window::Window,
     surface: wgpu::Surface<'window>,
+    gui: gui::Gui,
+}
+
+pub struct Visualizer<'window> {
+    /// `None` for visualizers that only render images
+    presenter: Option<Presenter<'window>>,
+    adapter_info: wgpu::AdapterInfo,
     device: wgpu::Device,
     queue: wgpu::Queue,
-    diffuse_texture: texture::Texture,
+    field_texture: texture::Texture,
+    /// Wave speed relative to free space, zero inside walls
+    medium_texture: texture::Texture,
+    colormap_texture: texture::Texture,
+    /// Name of the colormap currently uploaded to `colormap_texture`
+    colormap_name: String,
+    settings_buffer: wgpu::Buffer,
     dim: (u32, u32),
     pipeline: GraphicsPipeline,
+    height_field: heightfield::HeightField,
     settings: Settings,
     config: wgpu::SurfaceConfiguration,
+    hud: hud::Hud,
+    hud_visible: bool,
+    overlay: overlay::Overlay,
+    legend_visible: bool,
+    /// Whether the window has no area to draw to
+    minimized: bool,
+    /// Half extent of the simulation quad in normalized device coordinates
+    extent: (f32, f32),
+    /// Last known cursor position in physical pixels
+    cursor: Option<(f64, f64)>,
+    /// Whether the cursor currently drags the view
+    dragging: bool,
+    view: View,
+    timing: Timing,
+    range: range::RangeTracker,
+    /// Mode and range selection the tracker was last fed with
+    range_state: (Mode, range::AutoRange),
+    /// Positions of the probes in m
+    probes: Vec<(f64, f64)>,
+    /// Intensity along the screen, if there is one
+    profile: Option<screen::Profile>,
+    /// Mean power through the flux monitors
+    monitors: Vec<flux::Report>,
+    flux_visible: bool,
+    /// Energy flux drawn as arrows
+    flux: Option<flux::FluxField>,
 }
 
 pub struct Settings {
-    pub colors: (wgpu::Color, wgpu::Color),
+    pub colormap: colormap::Colormap,
+    pub mode: Mode,
+    /// Dynamic range shown in [`Mode::Decibel`]
+    pub db_range: f64,
+    pub auto_range: range::AutoRange,
+    /// Percentile used by [`range::AutoRange::Percentile`]
+    pub percentile: f64,
     pub clamp: f64,
+    pub render_mode: RenderMode,
+    /// Draw walls and tint media with a different wave speed
+    pub show_medium: bool,
+    /// Number of bands the color range is split into by contour lines, even
+    /// numbers include the nodal lines of signed quantities
+    pub contours: u32,
+    pub show_contours: bool,
+    /// Height of values at the end of the colormap in [`RenderMode::Surface`]
+    /// and [`RenderMode::RippleTank`], relative to the longer side of the simulation
+    pub height: f64,
+    /// Height of the lamp above the water in [`RenderMode::RippleTank`] in m
+    pub lamp_height: f64,
+    /// Distance of the screen below the water in [`RenderMode::RippleTank`] in m
+    pub screen_distance: f64,
     pub aspect_ratio: f64,
+    /// Physical size of the simulation in m
+    pub size: (f64, f64),
+}
+
+impl Settings {
+    /// Refractive index of water
+    const WATER: f64 = 1.33;
+
+    /// Switch the mode along with the default colormap of the new mode.
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+        self.colormap = mode.default_colormap();
+    }
+
+    /// Water height at the ends of the colormap in m.
+    fn water_height(&self) -> f64 {
+        self.height * self.size.0.max(self.size.1)
+    }
+
+    /// Distance over which rays refracted by a slope of the water spread in
+    /// the ripple tank, projected back onto the water surface.
+    ///
+    /// A slope deflects a ray by `(1 - 1 / n)` times the slope for small
+    /// angles, the diverging light of the lamp magnifies the pattern on the
+    /// screen by `(lamp_height + screen_distance) / lamp_height`.
+    fn focus(&self) -> f64 {
+        let magnification = (self.lamp_height + self.screen_distance) / self.lamp_height;
+        self.screen_distance * (1.0 - 1.0 / Self::WATER) / magnification
+    }
+}
+
+/// How the field is drawn.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum RenderMode {
+    /// Colormapped image seen from above
+    #[default]
+    Flat,
+    /// Lit 3D surface displaced by the field
+    Surface,
+    /// Light of a lamp projected through the water surface onto a screen
+    RippleTank,
+}
+
+impl RenderMode {
+    pub const ALL: [RenderMode; 3] = [
+        RenderMode::Flat,
+        RenderMode::Surface,
+        RenderMode::RippleTank,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            RenderMode::Flat => "flat",
+            RenderMode::Surface => "surface",
+            RenderMode::RippleTank => "ripple tank",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        Self::ALL[(self as usize + 1) % Self::ALL.len()]
+    }
+}
+
+/// Quantity derived from the field that is mapped to colors.
+///
+/// The discriminants match the constants in `shader.wgsl`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Mode {
+    /// Signed field `u`
+    #[default]
+    Field = 0,
+    /// Amplitude `|u|`
+    Amplitude = 1,
+    /// Instantaneous intensity `u^2`
+    Intensity = 2,
+    /// Time averaged intensity `<u^2>`
+    AveragedIntensity = 3,
+    /// Magnitude `|u|` in dB relative to the clamp value
+    Decibel = 4,
+    /// Phase `φ` of the time harmonic field `A cos(ωt + φ)` at the source frequency
+    Phase = 5,
+    /// Root mean square `sqrt(<u^2>)`
+    Rms = 6,
+    /// Largest magnitude `|u|` within the averaging window
+    Envelope = 7,
+    /// Time the envelope was reached at in seconds
+    PeakTime = 8,
+    /// Amplitude `A` of the time harmonic field at the source frequency
+    HarmonicAmplitude = 9,
+}
+
+impl Mode {
+    pub const ALL: [Mode; 10] = [
+        Mode::Field,
+        Mode::Amplitude,
+        Mode::Intensity,
+        Mode::AveragedIntensity,
+        Mode::Decibel,
+        Mode::Phase,
+        Mode::Rms,
+        Mode::Envelope,
+        Mode::PeakTime,
+        Mode::HarmonicAmplitude,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Mode::Field => "field",
+            Mode::Amplitude => "amplitude",
+            Mode::Intensity => "intensity",
+            Mode::AveragedIntensity => "averaged intensity",
+            Mode::Decibel => "magnitude [dB]",
+            Mode::Phase => "phase",
+            Mode::Rms => "rms",
+            Mode::Envelope => "envelope",
+            Mode::PeakTime => "peak time",
+            Mode::HarmonicAmplitude => "harmonic amplitude",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        Self::ALL[(self as usize + 1) % Self::ALL.len()]
+    }
+
+    /// Magnitude of a value in the units of the clamp value, if the mode has one.
+    fn magnitude(self, u: f64) -> Option<f64> {
+        match self {
+            Mode::Field | Mode::Amplitude | Mode::Decibel => Some(u.abs()),
+            Mode::Intensity => Some(u.powi(2)),
+            Mode::AveragedIntensity
+            | Mode::Rms
+            | Mode::Envelope
+            | Mode::PeakTime
+            | Mode::HarmonicAmplitude => Some(u),
+            Mode::Phase => None,
+        }
+    }
+
+    /// Values mapped to the ends of the colormap.
+    fn value_range(self, clamp: f64, db_range: f64) -> (f64, f64) {
+        match self {
+            Mode::Field => (-clamp, clamp),
+            Mode::Amplitude
+            | Mode::Intensity
+            | Mode::AveragedIntensity
+            | Mode::Rms
+            | Mode::Envelope
+            | Mode::PeakTime
+            | Mode::HarmonicAmplitude => (0.0, clamp),
+            Mode::Decibel => (-db_range, 0.0),
+            Mode::Phase => (-std::f64::consts::PI, std::f64::consts::PI),
+        }
+    }
+
+    fn format_value(self, value: f64) -> String {
+        match self {
+            Mode::Decibel => format!("{:.0} dB", value),
+            Mode::Phase => format!("{:.2} rad", value),
+            Mode::PeakTime => format!("{:.2e} s", value),
+            _ => format!("{:.2e}", value),
+        }
+    }
+
+    /// Colorblind safe colormap suited for the range of the mode.
+    pub fn default_colormap(self) -> colormap::Colormap {
+        let name = match self {
+            Mode::Field => colormap::Colormap::DEFAULT_DIVERGING,
+            Mode::Phase => "twilight",
+            _ => "magma",
+        };
+        colormap::Colormap::named(name).unwrap()
+    }
+}
+
+/// [`Settings`] as laid out in the uniform buffer of `shader.wgsl`
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Zeroable, bytemuck::Pod)]
+struct SettingsUniform {
+    clamp: f32,
+    mode: u32,
+    db_range: f32,
+    zoom: f32,
+    offset: [f32; 2],
+    nearest: u32,
+    height: f32,
+    size: [f32; 2],
+    focus: f32,
+    show_medium: u32,
+    contours: u32,
+    _padding: f32,
+}
+
+impl SettingsUniform {
+    fn new(settings: &Settings, view: &View, nearest: bool) -> Self {
+        Self {
+            clamp: settings.clamp as f32,
+            mode: settings.mode as u32,
+            db_range: settings.db_range as f32,
+            zoom: view.zoom,
+            offset: [view.offset.0, view.offset.1],
+            nearest: nearest as u32,
+            height: settings.water_height() as f32,
+            size: [settings.size.0 as f32, settings.size.1 as f32],
+            focus: settings.focus() as f32,
+            show_medium: settings.show_medium as u32,
+            contours: if settings.show_contours {
+                settings.contours
+            } else {
+                0
+            },
+            _padding: 0.0,
+        }
+    }
+}
+
+/// Zoom and pan applied to the simulation quad in the vertex stage.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct View {
+    zoom: f32,
+    /// Translation in normalized device coordinates
+    offset: (f32, f32),
+}
+
+impl Default for View {
+    fn default() -> Self {
+        Self {
+            zoom: 1.0,
+            offset: (0.0, 0.0),
+        }
+    }
+}
+
+impl View {
+    const MIN_ZOOM: f32 = 0.5;
+    const MAX_ZOOM: f32 = 1000.0;
+
+    /// Map normalized device coordinates to the untransformed quad.
+    fn inverse(&self, ndc: (f64, f64)) -> (f64, f64) {
+        (
+            (ndc.0 - self.offset.0 as f64) / self.zoom as f64,
+            (ndc.1 - self.offset.1 as f64) / self.zoom as f64,
+        )
+    }
+
+    /// Zoom by `factor`, keeping the point at `center` fixed.
+    fn zoom_at(&mut self, factor: f32, center: (f32, f32)) {
+        let zoom = (self.zoom * factor).clamp(Self::MIN_ZOOM, Self::MAX_ZOOM);
+        let factor = zoom / self.zoom;
+        self.offset = (
+            center.0 - (center.0 - self.offset.0) * factor,
+            center.1 - (center.1 - self.offset.1) * factor,
+        );
+        self.zoom = zoom;
+    }
+}
+
+/// State of the simulation shown in the HUD.
+pub struct Status {
+    pub time: f64,
+    pub energy: f64,
+    /// Steps advanced for the current frame
+    pub steps: u32,
+    pub pacing: pacing::Settings,
+    pub paused: bool,
+    pub reversed: bool,
+    pub courant: f64,
+}
+
+/// Smoothed frame rate and simulation speed.
+struct Timing {
+    last_frame: Instant,
+    last_time: f64,
+    fps: f64,
+    sim_rate: f64,
+}
+
+impl Timing {
+    /// Weight of the newest frame in the moving averages
+    const SMOOTHING: f64 = 0.05;
+
+    fn new() -> Self {
+        Self {
+            last_frame: Instant::now(),
+            last_time: 0.0,
+            fps: 0.0,
+            sim_rate: 0.0,
+        }
+    }
+
+    fn update(&mut self, time: f64) {
+        let now = Instant::now();
+        let elapsed = (now - self.last_frame).as_secs_f64();
+        if elapsed > 0.0 {
+            let fps = 1.0 / elapsed;
+            let sim_rate = (time - self.last_time) / elapsed;
+            self.fps += Self::SMOOTHING * (fps - self.fps);
+            self.sim_rate += Self::SMOOTHING * (sim_rate - self.sim_rate);
+        }
+        self.last_frame = now;
+        self.last_time = time;
+    }
 }
 
 struct GraphicsPipeline {
     render_pipeline: wgpu::RenderPipeline,
-    diffuse_bind_group: wgpu::BindGroup,
+    ripple_pipeline: wgpu::RenderPipeline,
+    field_bind_group: wgpu::BindGroup,
     vertex_buffer: wgpu::Buffer,
     index_buffer: wgpu::Buffer,
 }
 
 impl<'window> Visualizer<'window> {
+    const WHITE: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+    /// Format of the images rendered without a window
+    const OFFSCREEN_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
     pub async fn new(
         window: &'window winit::window::Window,
         dim: (u32, u32),
         settings: Settings,
-    ) -> Self {
-        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
-        let surface = instance.create_surface(window).unwrap();
-
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptionsBase {
-                power_preference: wgpu::PowerPreference::default(),
-                compatible_surface: Some(&surface),
-                force_fallback_adapter: false,
-            })
-            .await
-            .unwrap();
+        options: gpu::Options,
+    ) -> Result<Self, VisError> {
+        let instance = options.instance();
+        let surface = instance
+            .create_surface(window)
+            .map_err(VisError::CreateSurface)?;
 
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    label: None,
-                    required_features: wgpu::Features::default(),
-                    required_limits: wgpu::Limits::default(),
-                },
-                None,
-            )
+        let adapter = options
+            .request_adapter(&instance, Some(&surface))
             .await
-            .unwrap();
+            .ok_or(VisError::NoAdapter)?;
+        let (device, queue) = Self::request_device(&adapter).await?;
 
         let surface_caps = surface.get_capabilities(&adapter);
         log::debug!("Surface Capabilities: {:?}", surface_caps);
@@ -120,25 +528,160 @@ impl<'window> Visualizer<'window> {
         let config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
             format: surface_format,
-            width: window.inner_size().width,
-            height: window.inner_size().height,
-            present_mode: surface_caps.present_modes[0],
+            // the window may not be shown yet, surfaces can not be empty
+            width: window.inner_size().width.max(1),
+            height: window.inner_size().height.max(1),
+            present_mode: options.present_mode.resolve(&surface_caps.present_modes),
             alpha_mode: surface_caps.alpha_modes[0],
             view_formats: vec![],
-            desired_maximum_frame_latency: 15,
+            desired_maximum_frame_latency: options.frame_latency,
         };
         surface.configure(&device, &config);
 
+        let gui = gui::Gui::new(window, &device, config.format);
+        let presenter = Presenter {
+            window,
+            surface,
+            gui,
+        };
+        Ok(Self::with_device(
+            adapter.get_info(),
+            device,
+            queue,
+            config,
+            dim,
+            settings,
+            Some(presenter),
+        ))
+    }
+
+    /// Create a visualizer without a window, rendering only images of
+    /// `resolution` through [`Visualizer::render_image`].
+    pub async fn offscreen(
+        dim: (u32, u32),
+        settings: Settings,
+        options: gpu::Options,
+        resolution: (u32, u32),
+    ) -> Result<Self, VisError> {
+        let instance = options.instance();
+        let adapter = options
+            .request_adapter(&instance, None)
+            .await
+            .ok_or(VisError::NoAdapter)?;
+        let (device, queue) = Self::request_device(&adapter).await?;
+        check_resolution(&device, resolution)?;
+
+        // never configured, only describes the render target
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            format: Self::OFFSCREEN_FORMAT,
+            width: resolution.0,
+            height: resolution.1,
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+            view_formats: vec![],
+            desired_maximum_frame_latency: options.frame_latency,
+        };
+        let mut vis = Self::with_device(
+            adapter.get_info(),
+            device,
+            queue,
+            config,
+            dim,
+            settings,
+            None,
+        );
+        vis.set_resolution(resolution);
+        Ok(vis)
+    }
+
+    /// The adapter drawing the frames.
+    pub fn adapter_info(&self) -> &wgpu::AdapterInfo {
+        &self.adapter_info
+    }
+
+    async fn request_device(
+        adapter: &wgpu::Adapter,
+    ) -> Result<(wgpu::Device, wgpu::Queue), VisError> {
+        adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: None,
+                    required_features: wgpu::Features::default(),
+                    required_limits: wgpu::Limits::default(),
+                },
+                None,
+            )
+            .await
+            .map_err(VisError::RequestDevice)
+    }
+
+    /// Create the textures and pipelines drawing to targets described by `config`.
+    fn with_device(
+        adapter_info: wgpu::AdapterInfo,
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        config: wgpu::SurfaceConfiguration,
+        dim: (u32, u32),
+        settings: Settings,
+        presenter: Option<Presenter<'window>>,
+    ) -> Self {
         log::info!("Creating Texture");
-        let diffuse_texture =
-            crate::texture::Texture::test_texture(&device, &queue, "test image", (dim.0, dim.1));
+        let field_texture = texture::Texture::field_texture(&device, "field texture", dim);
+        let medium_texture = texture::Texture::field_texture(&device, "medium texture", dim);
+        medium_texture.write(
+            &queue,
+            bytemuck::cast_slice(&vec![1.0f32; (dim.0 * dim.1) as usize]),
+        );
+        let colormap_texture =
+            texture::Texture::lut_texture(&device, "colormap texture", colormap::LUT_SIZE as u32);
+        colormap_texture.write(&queue, bytemuck::cast_slice(&settings.colormap.lut()));
+        let colormap_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
         log::info!("Created Texture");
 
-        let texture_bind_group_layout =
+        let settings_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Settings Buffer"),
+            contents: bytemuck::cast_slice(&[SettingsUniform::new(
+                &settings,
+                &View::default(),
+                false,
+            )]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let field_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 entries: &[
                     wgpu::BindGroupLayoutEntry {
                         binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
                         visibility: wgpu::ShaderStages::FRAGMENT,
                         ty: wgpu::BindingType::Texture {
                             multisampled: false,
@@ -148,29 +691,51 @@ impl<'window> Visualizer<'window> {
                         count: None,
                     },
                     wgpu::BindGroupLayoutEntry {
-                        binding: 1,
+                        binding: 3,
                         visibility: wgpu::ShaderStages::FRAGMENT,
                         ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
                         count: None,
                     },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 4,
+                        visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        },
+                        count: None,
+                    },
                 ],
-                label: Some("texture_bind_group_layout"),
+                label: Some("field_bind_group_layout"),
             });
 
         log::info!("Creating Bind Group");
-        let diffuse_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &texture_bind_group_layout,
+        let field_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &field_bind_group_layout,
             entries: &[
                 wgpu::BindGroupEntry {
                     binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
+                    resource: wgpu::BindingResource::TextureView(&field_texture.view),
                 },
                 wgpu::BindGroupEntry {
                     binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
+                    resource: settings_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&colormap_texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(&colormap_sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(&medium_texture.view),
                 },
             ],
-            label: Some("diffuse_bind_group"),
+            label: Some("field_bind_group"),
         });
         log::info!("Created Bind Group");
 
@@ -182,53 +747,58 @@ impl<'window> Visualizer<'window> {
         let render_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("Render Pipeline Layout"),
-                bind_group_layouts: &[&texture_bind_group_layout],
+                bind_group_layouts: &[&field_bind_group_layout],
                 push_constant_ranges: &[],
             });
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[Vertex::desc()],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState {
-                        color: wgpu::BlendComponent::REPLACE,
-                        alpha: wgpu::BlendComponent::REPLACE,
-                    }),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                // Setting this to anything other than Fill requires Features::POLYGON_MODE_LINE
-                // or Features::POLYGON_MODE_POINT
-                polygon_mode: wgpu::PolygonMode::Fill,
-                // Requires Features::DEPTH_CLIP_CONTROL
-                unclipped_depth: false,
-                // Requires Features::CONSERVATIVE_RASTERIZATION
-                conservative: false,
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            // If the pipeline will be used with a multiview render pass, this
-            // indicates how many array layers the attachments will have.
-            multiview: None,
-        });
+        // the flat render modes share the quad and differ in the fragment shader
+        let create_pipeline = |label, fragment_entry_point| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&render_pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[Vertex::desc()],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: fragment_entry_point,
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: config.format,
+                        blend: Some(wgpu::BlendState {
+                            color: wgpu::BlendComponent::REPLACE,
+                            alpha: wgpu::BlendComponent::REPLACE,
+                        }),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: Some(wgpu::Face::Back),
+                    // Setting this to anything other than Fill requires Features::POLYGON_MODE_LINE
+                    // or Features::POLYGON_MODE_POINT
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    // Requires Features::DEPTH_CLIP_CONTROL
+                    unclipped_depth: false,
+                    // Requires Features::CONSERVATIVE_RASTERIZATION
+                    conservative: false,
+                },
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState {
+                    count: 1,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                // If the pipeline will be used with a multiview render pass, this
+                // indicates how many array layers the attachments will have.
+                multiview: None,
+            })
+        };
+        let render_pipeline = create_pipeline("Render Pipeline", "fs_main");
+        let ripple_pipeline = create_pipeline("Ripple Tank Pipeline", "fs_ripple");
 
         let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Vertex Buffer"),
@@ -240,25 +810,564 @@ impl<'window> Visualizer<'window> {
             contents: bytemuck::cast_slice(INDICES),
             usage: wgpu::BufferUsages::INDEX,
         });
+
+        let height_field = heightfield::HeightField::new(
+            &device,
+            config.format,
+            &shader,
+            &field_bind_group_layout,
+            dim,
+            (config.width, config.height),
+        );
+
+        let hud = hud::Hud::new(&device, &queue, config.format);
+        let overlay = overlay::Overlay::new(
+            &device,
+            config.format,
+            &colormap_texture.view,
+            &colormap_sampler,
+        );
+
         Self {
-            surface,
+            presenter,
+            adapter_info,
             device,
             queue,
-            diffuse_texture,
+            field_texture,
+            medium_texture,
+            colormap_name: settings.colormap.name.clone(),
+            colormap_texture,
+            settings_buffer,
             dim,
             pipeline: GraphicsPipeline {
                 render_pipeline,
-                diffuse_bind_group,
+                ripple_pipeline,
+                field_bind_group,
                 vertex_buffer,
                 index_buffer,
             },
-            settings,
+            height_field,
             config,
+            hud,
+            hud_visible: true,
+            overlay,
+            legend_visible: true,
+            minimized: false,
+            extent: (1.0, 1.0),
+            cursor: None,
+            dragging: false,
+            view: View::default(),
+            timing: Timing::new(),
+            range: range::RangeTracker::default(),
+            range_state: (settings.mode, settings.auto_range),
+            probes: vec![],
+            profile: None,
+            monitors: vec![],
+            flux_visible: false,
+            flux: None,
+            settings,
         }
     }
 
-    pub fn render(&self, field: &[f64]) {
-        let output = self.surface.get_current_texture().unwrap();
+    pub fn toggle_hud(&mut self) {
+        self.hud_visible = !self.hud_visible;
+    }
+
+    pub fn toggle_legend(&mut self) {
+        self.legend_visible = !self.legend_visible;
+    }
+
+    /// Switch to the next built in colormap.
+    pub fn cycle_colormap(&mut self) {
+        self.settings.colormap = self.settings.colormap.next();
+        log::info!("colormap: {}", self.settings.colormap.name);
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.settings.mode
+    }
+
+    pub fn cycle_mode(&mut self) {
+        self.settings.set_mode(self.settings.mode.next());
+        log::info!("mode: {}", self.settings.mode.name());
+    }
+
+    /// Upload the wave speed of every cell relative to free space, zero marks walls.
+    pub fn set_medium(&mut self, wave_speed: &[f64]) {
+        let wave_speed: Vec<f32> = wave_speed.iter().map(|&c| c as f32).collect();
+        self.medium_texture
+            .write(&self.queue, bytemuck::cast_slice(&wave_speed));
+    }
+
+    pub fn toggle_medium(&mut self) {
+        self.settings.show_medium = !self.settings.show_medium;
+    }
+
+    pub fn toggle_contours(&mut self) {
+        self.settings.show_contours = !self.settings.show_contours;
+    }
+
+    pub fn cycle_render_mode(&mut self) {
+        self.settings.render_mode = self.settings.render_mode.next();
+        log::info!("render mode: {}", self.settings.render_mode.name());
+    }
+
+    pub fn cycle_auto_range(&mut self) {
+        self.settings.auto_range = self.settings.auto_range.next();
+        log::info!("auto range: {}", self.settings.auto_range.name());
+    }
+
+    /// Scale the color range by hand, overriding any automatic range.
+    pub fn scale_clamp(&mut self, factor: f64) {
+        self.settings.auto_range = range::AutoRange::Manual;
+        self.settings.clamp *= factor;
+        log::info!("clamp: {:.4e}", self.settings.clamp);
+    }
+
+    /// Forget the magnitudes of previous frames.
+    pub fn reset_range(&mut self) {
+        self.range.reset();
+    }
+
+    fn update_range(&mut self, field: &[f64]) {
+        let state = (self.settings.mode, self.settings.auto_range);
+        if state != self.range_state {
+            self.range.reset();
+            self.range_state = state;
+        }
+        if self.settings.auto_range == range::AutoRange::Manual {
+            return;
+        }
+
+        let mode = self.settings.mode;
+        let Some(magnitudes) = field
+            .iter()
+            .map(|&u| mode.magnitude(u))
+            .collect::<Option<Vec<_>>>()
+        else {
+            return;
+        };
+        if let Some(clamp) = self.range.update(
+            self.settings.auto_range,
+            self.settings.percentile,
+            &magnitudes,
+        ) {
+            self.settings.clamp = clamp;
+        }
+    }
+
+    pub fn toggle_gui(&mut self) {
+        if let Some(presenter) = &mut self.presenter {
+            presenter.gui.visible = !presenter.gui.visible;
+        }
+    }
+
+    /// Pass a window event to the control panel, returns whether it was consumed.
+    pub fn handle_event(&mut self, event: &winit::event::WindowEvent) -> bool {
+        self.presenter
+            .as_mut()
+            .is_some_and(|presenter| presenter.gui.handle_event(presenter.window, event))
+    }
+
+    pub fn set_cursor(&mut self, position: Option<winit::dpi::PhysicalPosition<f64>>) {
+        /// Orbit angle per pixel dragged
+        const RAD_PER_PIXEL: f32 = 0.01;
+
+        let position = position.map(|p| (p.x, p.y));
+        if let (true, Some(old), Some(new)) = (self.dragging, self.cursor, position) {
+            match self.settings.render_mode {
+                RenderMode::Flat | RenderMode::RippleTank => {
+                    let (old, new) = (self.to_ndc(old), self.to_ndc(new));
+                    self.view.offset.0 += (new.0 - old.0) as f32;
+                    self.view.offset.1 += (new.1 - old.1) as f32;
+                }
+                RenderMode::Surface => self.height_field.camera.rotate(
+                    (old.0 - new.0) as f32 * RAD_PER_PIXEL,
+                    (new.1 - old.1) as f32 * RAD_PER_PIXEL,
+                ),
+            }
+        }
+        self.cursor = position;
+    }
+
+    /// Start or stop dragging the view with the cursor.
+    pub fn set_dragging(&mut self, dragging: bool) {
+        self.dragging = dragging;
+    }
+
+    /// Zoom in by `factor` around the cursor, or out for factors below 1.
+    pub fn zoom(&mut self, factor: f64) {
+        if self.settings.render_mode == RenderMode::Surface {
+            self.height_field.camera.zoom(factor as f32);
+            return;
+        }
+        let center = self.cursor.map_or((0.0, 0.0), |c| self.to_ndc(c));
+        self.view
+            .zoom_at(factor as f32, (center.0 as f32, center.1 as f32));
+    }
+
+    pub fn reset_view(&mut self) {
+        self.view = View::default();
+        self.height_field.camera = heightfield::Camera::default();
+    }
+
+    /// Map physical pixels to normalized device coordinates.
+    fn to_ndc(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        (
+            2.0 * x / self.config.width as f64 - 1.0,
+            1.0 - 2.0 * y / self.config.height as f64,
+        )
+    }
+
+    /// Whether a cell of the field covers more than one pixel.
+    ///
+    /// The ripple tank needs the interpolated field for its derivatives.
+    fn cells_visible(&self) -> bool {
+        if self.settings.render_mode == RenderMode::RippleTank {
+            return false;
+        }
+        let pixels_per_cell =
+            quad_width(self.extent.0, self.view.zoom, self.config.width) / self.dim.0 as f32;
+        pixels_per_cell > 1.0
+    }
+
+    /// Map the cursor to texture coordinates, if it is over the simulation.
+    fn cursor_tex_coords(&self) -> Option<(f64, f64)> {
+        if self.settings.render_mode == RenderMode::Surface {
+            return None;
+        }
+        let (ndc_x, ndc_y) = self.view.inverse(self.to_ndc(self.cursor?));
+        let u = (ndc_x / self.extent.0 as f64 + 1.0) / 2.0;
+        let v = (1.0 - ndc_y / self.extent.1 as f64) / 2.0;
+        if (0.0..1.0).contains(&u) && (0.0..1.0).contains(&v) {
+            Some((u, v))
+        } else {
+            None
+        }
+    }
+
+    /// Position of the cursor in m, if it is over the simulation.
+    pub fn cursor_position(&self) -> Option<(f64, f64)> {
+        let (u, v) = self.cursor_tex_coords()?;
+        Some((u * self.settings.size.0, v * self.settings.size.1))
+    }
+
+    /// Map a position in m to physical pixels.
+    fn to_pixels(&self, position: (f64, f64)) -> (f32, f32) {
+        let u = (position.0 / self.settings.size.0) as f32;
+        let v = (position.1 / self.settings.size.1) as f32;
+        let ndc_x = (2.0 * u - 1.0) * self.extent.0 * self.view.zoom + self.view.offset.0;
+        let ndc_y = (1.0 - 2.0 * v) * self.extent.1 * self.view.zoom + self.view.offset.1;
+        (
+            (ndc_x + 1.0) / 2.0 * self.config.width as f32,
+            (1.0 - ndc_y) / 2.0 * self.config.height as f32,
+        )
+    }
+
+    pub fn set_probes(&mut self, positions: &[(f64, f64)]) {
+        self.probes = positions.to_vec();
+    }
+
+    pub fn set_profile(&mut self, profile: Option<&screen::Profile>) {
+        self.profile = profile.cloned();
+    }
+
+    pub fn set_monitors(&mut self, monitors: &[flux::Report]) {
+        self.monitors = monitors.to_vec();
+    }
+
+    pub fn flux_visible(&self) -> bool {
+        self.flux_visible
+    }
+
+    pub fn toggle_flux(&mut self) {
+        self.flux_visible = !self.flux_visible;
+    }
+
+    pub fn set_flux(&mut self, flux: Option<&flux::FluxField>) {
+        self.flux = flux.cloned();
+    }
+
+    /// Arrows along the energy flux, spanning a block of the flux grid at
+    /// the reference magnitude, where the view is flat.
+    fn flux_arrows(&mut self) {
+        /// Arrows shorter than this fraction of the full length are left out
+        const MIN_LENGTH: f64 = 0.05;
+        let Some(flux) = self.flux.take() else {
+            return;
+        };
+        let reference = flux.reference_magnitude();
+        if !self.flux_visible
+            || self.settings.render_mode == RenderMode::Surface
+            || reference == 0.0
+        {
+            self.flux = Some(flux);
+            return;
+        }
+
+        let grid = flux.grid as usize;
+        let (size_x, size_y) = self.settings.size;
+        let block = (size_x / grid as f64).min(size_y / grid as f64);
+        for (i, &(sx, sy)) in flux.vectors.iter().enumerate() {
+            let length = sx.hypot(sy) / reference;
+            if length < MIN_LENGTH {
+                continue;
+            }
+            let (x, y) = (
+                sx / reference / length.max(1.0),
+                sy / reference / length.max(1.0),
+            );
+            // arrows centered on their block
+            let center = (
+                ((i % grid) as f64 + 0.5) * size_x / grid as f64,
+                ((i / grid) as f64 + 0.5) * size_y / grid as f64,
+            );
+            let half = (x * block * 0.45, y * block * 0.45);
+            let tail = self.to_pixels((center.0 - half.0, center.1 - half.1));
+            let tip = self.to_pixels((center.0 + half.0, center.1 + half.1));
+
+            let (dx, dy) = (tip.0 - tail.0, tip.1 - tail.1);
+            let head = 0.35;
+            self.overlay.line(tail, tip, 1.5, Self::WHITE);
+            for side in [-1.0, 1.0] {
+                let barb = (
+                    tip.0 - head * (dx - side * 0.5 * dy),
+                    tip.1 - head * (dy + side * 0.5 * dx),
+                );
+                self.overlay.line(tip, barb, 1.5, Self::WHITE);
+            }
+        }
+        self.flux = Some(flux);
+    }
+
+    /// Mark the screen with a line, the flux monitors with numbered outlines
+    /// and the probes with numbered squares, where the view is flat.
+    fn markers(&mut self, labels: &mut Vec<hud::Label>) {
+        const MONITOR_COLOR: [f32; 4] = [0.3, 1.0, 0.5, 1.0];
+        if self.settings.render_mode == RenderMode::Surface {
+            return;
+        }
+        for i in 0..self.monitors.len() {
+            let sides = self.monitors[i].shape.sides();
+            for &(start, end, _) in &sides {
+                let (start, end) = (self.to_pixels(start), self.to_pixels(end));
+                self.overlay.line(start, end, 2.0, MONITOR_COLOR);
+            }
+            let (x, y) = self.to_pixels(sides[0].0);
+            labels.push(hud::Label {
+                text: format!("F{}", i + 1),
+                position: (x + 8.0, y - 24.0),
+            });
+        }
+        if let Some(screen) = self.profile.as_ref().map(|profile| profile.screen) {
+            let (start, end) = (self.to_pixels(screen.start), self.to_pixels(screen.end));
+            self.overlay.line(start, end, 2.0, Self::WHITE);
+        }
+        for i in 0..self.probes.len() {
+            let (x, y) = self.to_pixels(self.probes[i]);
+            self.overlay
+                .rect((x - 4.0, y - 4.0), (x + 4.0, y + 4.0), [0.0, 0.0, 0.0, 1.0]);
+            self.overlay
+                .rect((x - 2.0, y - 2.0), (x + 2.0, y + 2.0), Self::WHITE);
+            labels.push(hud::Label {
+                text: (i + 1).to_string(),
+                position: (x + 8.0, y - 24.0),
+            });
+        }
+    }
+
+    fn hud_text(&self, field: &[f64], status: &Status) -> String {
+        let mut text = format!(
+            "t:          {:.4e} s{}{}\n\
+             steps:      {} / frame ({})\n\
+             fps:        {:.1}\n\
+             sim rate:   {:.3e} s/s\n\
+             energy:     {:.4e}\n\
+             mode:       {}\n\
+             range:      {:.4e} ({})",
+            status.time,
+            if status.paused { " (paused)" } else { "" },
+            if status.reversed { " (reversed)" } else { "" },
+            status.steps,
+            status.pacing.describe(),
+            self.timing.fps,
+            self.timing.sim_rate,
+            status.energy,
+            self.settings.mode.name(),
+            self.settings.clamp,
+            self.settings.auto_range.name(),
+        );
+        let ratio = |ratio: Option<f64>| ratio.map_or("–".to_string(), |r| format!("{:.3}", r));
+        for (i, report) in self.monitors.iter().enumerate() {
+            text += &format!(
+                "\nflux F{}:    {:.3e} fwd, {:.3e} back, R = {}",
+                i + 1,
+                report.forward,
+                report.backward,
+                ratio(report.reflection()),
+            );
+            // the first monitor is the reference of the others
+            if i > 0 {
+                text += &format!(", T = {}", ratio(report.transmission(&self.monitors[0])));
+            }
+        }
+        if let Some((u, v)) = self.cursor_tex_coords() {
+            let col = (u * self.dim.0 as f64) as usize;
+            let row = (v * self.dim.1 as f64) as usize;
+            text += &format!(
+                "\ncursor:     ({:.3} m, {:.3} m) value = {:.4e}",
+                u * self.settings.size.0,
+                v * self.settings.size.1,
+                field[row * self.dim.0 as usize + col],
+            );
+        }
+        text
+    }
+
+    /// Draw a colorbar for the current mapping and a scale bar in meters,
+    /// where they apply to the render mode, and the plot of the screen.
+    fn legend(&mut self, labels: &mut Vec<hud::Label>) {
+        match self.settings.render_mode {
+            RenderMode::Flat => {
+                self.colorbar(labels);
+                self.scale_bar(labels);
+            }
+            RenderMode::Surface => self.colorbar(labels),
+            // the brightness does not follow the colormap
+            RenderMode::RippleTank => self.scale_bar(labels),
+        }
+        self.profile_plot(labels);
+    }
+
+    /// Plot of the intensity along the screen at the bottom, left of the
+    /// colorbar, with the Fraunhofer prediction if there is one.
+    fn profile_plot(&mut self, labels: &mut Vec<hud::Label>) {
+        const PREDICTION_COLOR: [f32; 4] = [1.0, 0.6, 0.2, 1.0];
+        let Some(profile) = self.profile.take() else {
+            return;
+        };
+        let (width, height) = (self.config.width as f32, self.config.height as f32);
+        let min = (width - 150.0 - width * 0.35, height - 170.0);
+        let max = (width - 150.0, height - 20.0);
+        self.overlay.rect(min, max, [0.0, 0.0, 0.0, 0.5]);
+
+        let peak = profile.intensity.iter().fold(0.0, |a: f64, &b| a.max(b));
+        let length = profile.screen.length().max(f64::MIN_POSITIVE);
+        let point = |s: f64, intensity: f64| {
+            let y = if peak > 0.0 { intensity / peak } else { 0.0 };
+            (
+                min.0 + (s / length) as f32 * (max.0 - min.0),
+                max.1 - (y as f32).clamp(0.0, 1.0) * (max.1 - min.1),
+            )
+        };
+        let mut polyline = |values: &[f64], width: f32, color: [f32; 4]| {
+            let points: Vec<_> = profile
+                .distance
+                .iter()
+                .zip(values)
+                .map(|(&s, &intensity)| point(s, intensity))
+                .collect();
+            for segment in points.windows(2) {
+                self.overlay.line(segment[0], segment[1], width, color);
+            }
+        };
+        if let Some(prediction) = &profile.prediction {
+            polyline(prediction, 1.5, PREDICTION_COLOR);
+        }
+        polyline(&profile.intensity, 2.0, Self::WHITE);
+
+        labels.push(hud::Label {
+            text: format!("screen mean intensity, peak {:.2e}", peak),
+            position: (min.0, min.1 - 30.0),
+        });
+        self.profile = Some(profile);
+    }
+
+    fn colorbar(&mut self, labels: &mut Vec<hud::Label>) {
+        const TICKS: usize = 5;
+        let (width, height) = (self.config.width as f32, self.config.height as f32);
+
+        // colorbar along the right edge of the window
+        let min = (width - 130.0, height * 0.2);
+        let max = (width - 110.0, height * 0.8);
+        self.overlay.rect(
+            (min.0 - 2.0, min.1 - 2.0),
+            (max.0 + 2.0, max.1 + 2.0),
+            Self::WHITE,
+        );
+        self.overlay.gradient(min, max, (0.0, 1.0));
+
+        let mode = self.settings.mode;
+        let (low, high) = mode.value_range(self.settings.clamp, self.settings.db_range);
+        for i in 0..TICKS {
+            let f = i as f64 / (TICKS - 1) as f64;
+            let y = max.1 - f as f32 * (max.1 - min.1);
+            self.overlay
+                .rect((max.0, y - 1.0), (max.0 + 6.0, y + 1.0), Self::WHITE);
+            labels.push(hud::Label {
+                text: mode.format_value(low + f * (high - low)),
+                position: (max.0 + 10.0, y - 11.0),
+            });
+        }
+        labels.push(hud::Label {
+            text: mode.name().to_string(),
+            position: (min.0, min.1 - 32.0),
+        });
+    }
+
+    /// Scale bar in the bottom left corner of the visible part of the simulation.
+    fn scale_bar(&mut self, labels: &mut Vec<hud::Label>) {
+        let ((left, bottom), length, pixels) = self.scale_bar_layout();
+        self.overlay
+            .rect((left, bottom - 6.0), (left + pixels, bottom), Self::WHITE);
+        labels.push(hud::Label {
+            text: format!("{} m", length),
+            position: (left, bottom - 30.0),
+        });
+    }
+
+    /// Bottom left corner of the scale bar, its length in m and in pixels.
+    fn scale_bar_layout(&self) -> ((f32, f32), f64, f32) {
+        let (width, height) = (self.config.width as f32, self.config.height as f32);
+        let View { zoom, offset } = self.view;
+        let left = ((1.0 + offset.0 - self.extent.0 * zoom) / 2.0 * width).max(0.0) + 20.0;
+        let bottom = ((1.0 - offset.1 + self.extent.1 * zoom) / 2.0 * height).min(height) - 20.0;
+        let pixels_per_meter =
+            quad_width(self.extent.0, zoom, self.config.width) / self.settings.size.0 as f32;
+        let length = nice_length(width as f64 / 5.0 / pixels_per_meter as f64);
+        ((left, bottom), length, length as f32 * pixels_per_meter)
+    }
+
+    /// Draw a frame to the window, skipping it while the window is minimized
+    /// or the surface has to be reconfigured.
+    pub fn render(
+        &mut self,
+        field: &[f64],
+        status: &Status,
+        controls: &mut gui::Controls,
+    ) -> Result<(), VisError> {
+        self.timing.update(status.time);
+        self.update_range(field);
+        let Some(presenter) = &mut self.presenter else {
+            return Ok(());
+        };
+        if self.minimized {
+            return Ok(());
+        }
+
+        let output = match presenter.surface.get_current_texture() {
+            Ok(output) => output,
+            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                log::info!("Surface lost or outdated, reconfiguring");
+                presenter.surface.configure(&self.device, &self.config);
+                return Ok(());
+            }
+            Err(wgpu::SurfaceError::Timeout) => {
+                log::warn!("Timed out waiting for the surface, skipping frame");
+                return Ok(());
+            }
+            Err(e) => return Err(VisError::Surface(e)),
+        };
         let view = output
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
@@ -269,36 +1378,153 @@ impl<'window> Visualizer<'window> {
                 label: Some("Render Encoder"),
             });
 
-        let size = wgpu::Extent3d {
-            width: self.dim.0,
-            height: self.dim.1,
-            depth_or_array_layers: 1,
-        };
+        let gui_visible = presenter.gui.visible;
+        if gui_visible {
+            presenter.gui.prepare(
+                presenter.window,
+                &self.device,
+                &self.queue,
+                &mut encoder,
+                controls,
+                &mut self.settings,
+                status,
+            );
+        }
 
-        log::debug!("Converting to Texture");
-        let texture = self.field2texture(field);
+        self.draw(&mut encoder, &view, field, status, gui_visible);
 
-        self.queue.write_texture(
-            wgpu::ImageCopyTexture {
-                aspect: wgpu::TextureAspect::All,
-                texture: &self.diffuse_texture.texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-            },
-            &texture,
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(self.dim.0 * 4),
-                rows_per_image: Some(self.dim.1),
-            },
-            size,
+        self.queue.submit(std::iter::once(encoder.finish()));
+        output.present();
+        self.hud.trim();
+        if let Some(presenter) = &mut self.presenter {
+            presenter.gui.cleanup();
+        }
+        Ok(())
+    }
+
+    /// Render a frame of `resolution` into an image, independent of the size
+    /// of the window. The control panel is left out.
+    pub fn render_image(
+        &mut self,
+        field: &[f64],
+        status: &Status,
+        resolution: (u32, u32),
+    ) -> Result<image::RgbaImage, VisError> {
+        check_resolution(&self.device, resolution)?;
+        self.update_range(field);
+        let previous = (self.config.width, self.config.height);
+        if resolution != previous {
+            self.set_resolution(resolution);
+        }
+
+        let target = texture::Texture::render_target(
+            &self.device,
+            "image texture",
+            resolution,
+            self.config.format,
         );
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Image Encoder"),
+            });
+        self.draw(&mut encoder, &target.view, field, status, false);
+        self.queue.submit(std::iter::once(encoder.finish()));
+        self.hud.trim();
+        let pixels = target.read(&self.device, &self.queue);
+
+        if resolution != previous {
+            self.set_resolution(previous);
+        }
+        let mut pixels = pixels.map_err(VisError::Readback)?;
+        if matches!(
+            self.config.format,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb
+        ) {
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+        Ok(
+            image::RgbaImage::from_raw(resolution.0, resolution.1, pixels)
+                .expect("Readback holds four bytes per pixel"),
+        )
+    }
+
+    /// Record the passes drawing the field and its overlays into `view`,
+    /// which matches the current resolution.
+    fn draw(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        field: &[f64],
+        status: &Status,
+        gui_visible: bool,
+    ) {
+        let mut labels = vec![];
+        if self.hud_visible {
+            labels.push(hud::Label {
+                text: self.hud_text(field, status),
+                position: (10.0, 10.0),
+            });
+        }
+        if self.legend_visible {
+            self.legend(&mut labels);
+        }
+        self.flux_arrows();
+        self.markers(&mut labels);
+        for label in &labels {
+            // keep the text readable on bright colormaps
+            let (width, height) = label.size();
+            self.overlay.rect(
+                (label.position.0 - 4.0, label.position.1 - 2.0),
+                (
+                    label.position.0 + width + 4.0,
+                    label.position.1 + height + 2.0,
+                ),
+                [0.0, 0.0, 0.0, 0.5],
+            );
+        }
+        let resolution = (self.config.width, self.config.height);
+        self.overlay.prepare(&self.device, resolution);
+        self.hud
+            .prepare(&self.device, &self.queue, resolution, &labels);
+
+        log::debug!("Uploading field");
+        let field: Vec<f32> = field.iter().map(|&u| u as f32).collect();
+        self.field_texture
+            .write(&self.queue, bytemuck::cast_slice(&field));
+        if self.colormap_name != self.settings.colormap.name {
+            self.colormap_texture.write(
+                &self.queue,
+                bytemuck::cast_slice(&self.settings.colormap.lut()),
+            );
+            self.colormap_name = self.settings.colormap.name.clone();
+        }
+        self.queue.write_buffer(
+            &self.settings_buffer,
+            0,
+            bytemuck::cast_slice(&[SettingsUniform::new(
+                &self.settings,
+                &self.view,
+                self.cells_visible(),
+            )]),
+        );
+        if self.settings.render_mode == RenderMode::Surface {
+            self.height_field.prepare(
+                &self.queue,
+                resolution,
+                self.settings.aspect_ratio as f32,
+                self.settings.height as f32,
+            );
+        }
 
         {
+            let surface = self.settings.render_mode == RenderMode::Surface;
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
+                    view,
                     resolve_target: None,
                     ops: wgpu::Operations {
                         load: wgpu::LoadOp::Clear(wgpu::Color {
@@ -310,43 +1536,85 @@ impl<'window> Visualizer<'window> {
                         store: wgpu::StoreOp::Store,
                     },
                 })],
-                depth_stencil_attachment: None,
+                depth_stencil_attachment: surface.then(|| self.height_field.depth_attachment()),
                 occlusion_query_set: None,
                 timestamp_writes: None,
             });
 
-            let num_indices = INDICES.len() as u32;
+            if surface {
+                self.height_field
+                    .render(&mut render_pass, &self.pipeline.field_bind_group);
+            } else {
+                let num_indices = INDICES.len() as u32;
 
-            render_pass.set_pipeline(&self.pipeline.render_pipeline);
-            render_pass.set_bind_group(0, &self.pipeline.diffuse_bind_group, &[]);
-            render_pass.set_vertex_buffer(0, self.pipeline.vertex_buffer.slice(..));
-            render_pass.set_index_buffer(
-                self.pipeline.index_buffer.slice(..),
-                wgpu::IndexFormat::Uint16,
-            );
-            render_pass.draw_indexed(0..num_indices, 0, 0..1);
+                render_pass.set_pipeline(match self.settings.render_mode {
+                    RenderMode::RippleTank => &self.pipeline.ripple_pipeline,
+                    _ => &self.pipeline.render_pipeline,
+                });
+                render_pass.set_bind_group(0, &self.pipeline.field_bind_group, &[]);
+                render_pass.set_vertex_buffer(0, self.pipeline.vertex_buffer.slice(..));
+                render_pass.set_index_buffer(
+                    self.pipeline.index_buffer.slice(..),
+                    wgpu::IndexFormat::Uint16,
+                );
+                render_pass.draw_indexed(0..num_indices, 0, 0..1);
+            }
         }
 
-        self.queue.submit(std::iter::once(encoder.finish()));
-        output.present();
+        // overlays are drawn without a depth buffer
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Overlay Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+
+            self.overlay.render(&mut render_pass);
+            self.hud.render(&mut render_pass);
+            if let (true, Some(presenter)) = (gui_visible, &self.presenter) {
+                presenter.gui.render(&mut render_pass);
+            }
+        }
     }
 
     pub fn resize(&mut self, size: winit::dpi::PhysicalSize<u32>) {
-        self.config.width = size.width;
-        self.config.height = size.height;
-        self.surface.configure(&self.device, &self.config);
+        // minimized windows report a size of zero, keep the last configuration
+        self.minimized = size.width == 0 || size.height == 0;
+        if self.minimized {
+            return;
+        }
+        self.set_resolution((size.width, size.height));
+        if let Some(presenter) = &self.presenter {
+            presenter.surface.configure(&self.device, &self.config);
+        }
+    }
+
+    /// Adapt the quad and depth buffer to a target of `resolution`.
+    fn set_resolution(&mut self, resolution: (u32, u32)) {
+        (self.config.width, self.config.height) = resolution;
+        self.height_field.resize(&self.device, resolution);
 
         // calculate new vertices to keep aspect ratio
         // simulation aspect ratio
         let sim_ar = self.settings.aspect_ratio as f32;
-        // window aspect ratio
-        let win_ar = size.width as f32 / size.height as f32;
+        // target aspect ratio
+        let win_ar = resolution.0 as f32 / resolution.1 as f32;
 
         let (horizontal, vertical) = if sim_ar < win_ar {
             (sim_ar / win_ar, 1.0)
         } else {
             (1.0, win_ar / sim_ar)
         };
+        self.extent = (horizontal, vertical);
 
         // construct vertices
         let vertices = &[
@@ -377,34 +1645,233 @@ impl<'window> Visualizer<'window> {
             });
         self.pipeline.vertex_buffer = vertex_buffer;
     }
+}
 
-    fn field2texture(&self, field: &[f64]) -> Vec<u8> {
-        let mut casted = vec![0; (self.dim.0 * self.dim.1 * 4) as usize];
-        for (n, node) in field.iter().enumerate() {
-            // translate to rgba values
-            let low = self.settings.colors.0;
-            let high = self.settings.colors.1;
-            let factor = 1.0 / self.settings.clamp;
-
-            let r;
-            let g;
-            let b;
-            if *node < 0.0 {
-                // interpolate between low and 0
-                r = factor * node.abs() * low.r * 255.0;
-                g = factor * node.abs() * low.g * 255.0;
-                b = factor * node.abs() * low.b * 255.0;
-            } else {
-                // interpolate between 0 and high
-                r = factor * node.abs() * high.r * 255.0;
-                g = factor * node.abs() * high.g * 255.0;
-                b = factor * node.abs() * high.b * 255.0;
+/// Fail for images of `resolution` that are empty or larger than the
+/// textures of `device`.
+fn check_resolution(device: &wgpu::Device, resolution: (u32, u32)) -> Result<(), VisError> {
+    let max = device.limits().max_texture_dimension_2d;
+    if (1..=max).contains(&resolution.0) && (1..=max).contains(&resolution.1) {
+        Ok(())
+    } else {
+        Err(VisError::Resolution { resolution, max })
+    }
+}
+
+/// Width in pixels of the simulation quad spanning `±extent` in normalized
+/// device coordinates, zoomed by `zoom` on a target `width` pixels wide.
+fn quad_width(extent: f32, zoom: f32, width: u32) -> f32 {
+    extent * zoom * width as f32
+}
+
+/// Largest length of the form `{1, 2, 5} * 10^n` not exceeding `max`.
+fn nice_length(max: f64) -> f64 {
+    let magnitude = 10f64.powf(max.log10().floor());
+    [5.0, 2.0, 1.0]
+        .into_iter()
+        .map(|m| m * magnitude)
+        .find(|&length| length <= max)
+        .unwrap_or(magnitude)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nice_length() {
+        assert_eq!(nice_length(2.0), 2.0);
+        assert_eq!(nice_length(4.9), 2.0);
+        assert_eq!(nice_length(7.0), 5.0);
+        assert_eq!(nice_length(10.0), 10.0);
+        assert_eq!(nice_length(0.03), 0.02);
+    }
+
+    #[test]
+    fn test_zoom_at() {
+        let mut view = View::default();
+        view.zoom_at(2.0, (0.5, -0.5));
+        assert_eq!(view.zoom, 2.0);
+        // the point under the cursor stays in place
+        assert_eq!(view.inverse((0.5, -0.5)), (0.5, -0.5));
+        assert_eq!(view.inverse((0.0, 0.0)), (0.25, -0.25));
+
+        view.zoom_at(1e-6, (0.0, 0.0));
+        assert_eq!(view.zoom, View::MIN_ZOOM);
+    }
+
+    /// Cells along each side of the rendered fields
+    const N: u32 = 64;
+
+    /// Visualizer rendering images of 160 x 120 px on a software adapter,
+    /// `None` if the machine has none.
+    fn offscreen_visualizer(
+        render_mode: RenderMode,
+        show_contours: bool,
+    ) -> Option<Visualizer<'static>> {
+        let settings = Settings {
+            colormap: Mode::Field.default_colormap(),
+            mode: Mode::Field,
+            db_range: 60.0,
+            auto_range: range::AutoRange::Manual,
+            percentile: 99.0,
+            clamp: 1.0,
+            render_mode,
+            show_medium: true,
+            contours: 10,
+            show_contours,
+            height: 0.2,
+            lamp_height: 10.0,
+            screen_distance: 0.05,
+            aspect_ratio: 1.0,
+            size: (10.0, 10.0),
+        };
+        let options = gpu::Options {
+            backend: gpu::Backend::All,
+            power_preference: gpu::PowerPreference::None,
+            force_fallback_adapter: true,
+            present_mode: gpu::PresentMode::Vsync,
+            frame_latency: 2,
+        };
+        match pollster::block_on(Visualizer::offscreen((N, N), settings, options, (160, 120))) {
+            Ok(vis) => Some(vis),
+            Err(e) => {
+                eprintln!("skipping offscreen rendering: {}", e);
+                None
             }
+        }
+    }
+
+    /// Render a circular wave with a wall through it on a software adapter,
+    /// `None` if the machine has none.
+    fn render_offscreen(render_mode: RenderMode, show_contours: bool) -> Option<image::RgbaImage> {
+        let mut vis = offscreen_visualizer(render_mode, show_contours)?;
+        vis.toggle_hud();
+        vis.toggle_legend();
+
+        let cells = (0..N * N).map(|i| {
+            let x = (i % N) as f64 / N as f64 - 0.5;
+            let y = (i / N) as f64 / N as f64 - 0.5;
+            (x, y)
+        });
+        let wave_speed: Vec<f64> = cells
+            .clone()
+            .map(|(x, _)| if (x - 0.25).abs() < 0.02 { 0.0 } else { 1.0 })
+            .collect();
+        vis.set_medium(&wave_speed);
+        let field: Vec<f64> = cells
+            .map(|(x, y)| {
+                let r = x.hypot(y);
+                (r * 40.0).cos() * (-4.0 * r).exp()
+            })
+            .collect();
+
+        Some(vis.render_image(&field, &status(), (160, 120)).unwrap())
+    }
+
+    fn status() -> Status {
+        Status {
+            time: 0.0,
+            energy: 0.0,
+            steps: 1,
+            pacing: pacing::Settings {
+                pacing: pacing::Pacing::Steps,
+                steps_per_frame: 1,
+                rate: 1.0,
+                target_fps: 30.0,
+            },
+            paused: false,
+            reversed: false,
+            courant: 0.1,
+        }
+    }
+
+    /// Compare `image` to `tests/golden/<name>.png`, allowing the small
+    /// differences between rasterizers. Set `UPDATE_GOLDEN` to rewrite it.
+    fn assert_golden(name: &str, image: &image::RgbaImage) {
+        let path = format!("{}/tests/golden/{}.png", env!("CARGO_MANIFEST_DIR"), name);
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            image.save(&path).unwrap();
+            return;
+        }
+        let golden = image::open(&path)
+            .unwrap_or_else(|e| panic!("Failed to open {}: {}", path, e))
+            .to_rgba8();
+        assert_eq!(image.dimensions(), golden.dimensions());
+
+        let differing = image
+            .pixels()
+            .zip(golden.pixels())
+            .filter(|(a, b)| a.0.iter().zip(b.0).any(|(&a, b)| a.abs_diff(b) > 8))
+            .count();
+        let pixels = (image.width() * image.height()) as usize;
+        assert!(
+            differing * 200 <= pixels,
+            "{} of {} pixels differ from {}",
+            differing,
+            pixels,
+            path
+        );
+    }
+
+    #[test]
+    fn test_render_flat() {
+        if let Some(image) = render_offscreen(RenderMode::Flat, true) {
+            assert_golden("flat", &image);
+        }
+    }
+
+    #[test]
+    fn test_render_surface() {
+        if let Some(image) = render_offscreen(RenderMode::Surface, false) {
+            assert_golden("surface", &image);
+        }
+    }
+
+    #[test]
+    fn test_render_ripple_tank() {
+        if let Some(image) = render_offscreen(RenderMode::RippleTank, false) {
+            assert_golden("ripple_tank", &image);
+        }
+    }
+
+    #[test]
+    fn test_render_image_resolution() {
+        let Some(mut vis) = offscreen_visualizer(RenderMode::Flat, false) else {
+            return;
+        };
+        let field = vec![0.0; (N * N) as usize];
+        let max = vis.device.limits().max_texture_dimension_2d;
+        for resolution in [(0, 120), (160, 0), (max + 1, 120)] {
+            assert!(matches!(
+                vis.render_image(&field, &status(), resolution),
+                Err(VisError::Resolution { .. })
+            ));
+        }
+        // the visualizer keeps working
+        assert!(vis.render_image(&field, &status(), (16, 12)).is_ok());
+    }
+
+    #[test]
+    fn test_scale_bar() {
+        let Some(mut vis) = offscreen_visualizer(RenderMode::Flat, false) else {
+            return;
+        };
+        vis.toggle_hud();
+        vis.toggle_legend();
+        let field = vec![0.0; (N * N) as usize];
+        for zoom in [1.0, 0.5] {
+            vis.reset_view();
+            vis.view.zoom_at(zoom, (0.0, 0.0));
+            let image = vis.render_image(&field, &status(), (160, 120)).unwrap();
 
-            casted[n * 4] = r as u8;
-            casted[n * 4 + 1] = g as u8;
-            casted[n * 4 + 2] = b as u8;
+            // the 10 m wide quad differs from the background in the middle row
+            let background = *image.get_pixel(0, 60);
+            let quad = (0..160)
+                .filter(|&x| image.get_pixel(x, 60) != &background)
+                .count() as f64;
+            let (_, length, pixels) = vis.scale_bar_layout();
+            assert!((pixels as f64 - length * quad / 10.0).abs() <= 1.0);
         }
-        casted
     }
 }
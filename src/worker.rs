@@ -0,0 +1,407 @@
+use crate::{flux, gui, pacing, probe, scene, screen, sim, spectrum, vis};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Changes to the simulation requested by the UI.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Command {
+    Controls(gui::Controls),
+    Pause(bool),
+    /// Advance a single step while paused
+    Step,
+    Reset,
+    Reverse,
+    /// Quantity the published frames hold
+    Show(vis::Mode),
+    /// Whether the published frames hold the energy flux
+    ShowFlux(bool),
+    /// Place a probe at a position in m
+    AddProbe((f64, f64)),
+    /// Save the samples and spectra of all probes, the statistics of the
+    /// field and the power through the flux monitors as CSV
+    Export,
+}
+
+/// Snapshot of the simulation published after every batch of steps.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame {
+    /// Values of the requested quantity for every cell
+    pub data: Vec<f64>,
+    pub mode: vis::Mode,
+    /// Steps advanced since the previous frame
+    pub steps: u32,
+    pub time: f64,
+    pub energy: f64,
+    pub reversed: bool,
+    pub courant: f64,
+    /// Positions of the probes in m
+    pub probes: Vec<(f64, f64)>,
+    /// Intensity along the screen, if there is one
+    pub profile: Option<screen::Profile>,
+    /// Energy flux, if requested
+    pub flux: Option<flux::FluxField>,
+    /// Mean power through the flux monitors
+    pub monitors: Vec<flux::Report>,
+}
+
+/// Measurements derived from the recorded data.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Analysis {
+    pub spectrum: spectrum::Settings,
+    /// Slits whose far field is compared to the screen
+    pub slits: Option<scene::Slits>,
+}
+
+/// Runs the simulation on its own thread, so slow steps do not block input
+/// and drawing.
+///
+/// Frames are passed through a channel holding a single frame: the solver
+/// computes the next frame while the UI draws the current one and waits
+/// only once it is a full frame ahead. The thread ends when the `Worker`
+/// is dropped.
+pub struct Worker {
+    commands: mpsc::Sender<Command>,
+    frames: mpsc::Receiver<Frame>,
+}
+
+/// State owned by the simulation thread.
+struct Runner {
+    sim: sim::Simulation,
+    controls: gui::Controls,
+    pacer: pacing::Pacer,
+    /// When the steps of the current frame were determined
+    last_frame: Instant,
+    /// Steps advanced for the current frame
+    steps: u32,
+    paused: bool,
+    mode: vis::Mode,
+    /// Whether frames hold the energy flux
+    flux: bool,
+    /// Whether the state changed since the last published frame
+    changed: bool,
+    analysis: Analysis,
+    commands: mpsc::Receiver<Command>,
+    frames: mpsc::SyncSender<Frame>,
+}
+
+/// Advance `sim` by `steps` steps on the calling thread and take a frame of
+/// it, for rendering without a window.
+pub fn run_to_frame(
+    sim: sim::Simulation,
+    controls: gui::Controls,
+    mode: vis::Mode,
+    analysis: Analysis,
+    steps: u32,
+) -> Frame {
+    let (_, commands) = mpsc::channel();
+    let (frames, _) = mpsc::sync_channel(1);
+    let mut runner = Runner::new(sim, controls, mode, analysis, commands, frames);
+    runner.sim.multi_step(steps, controls.dt);
+    runner.steps = steps;
+    runner.frame()
+}
+
+impl Frame {
+    /// State shown alongside the frame.
+    pub fn status(&self, pacing: pacing::Settings, paused: bool) -> vis::Status {
+        vis::Status {
+            time: self.time,
+            energy: self.energy,
+            steps: self.steps,
+            pacing,
+            paused,
+            reversed: self.reversed,
+            courant: self.courant,
+        }
+    }
+}
+
+impl Worker {
+    pub fn spawn(
+        sim: sim::Simulation,
+        controls: gui::Controls,
+        mode: vis::Mode,
+        analysis: Analysis,
+    ) -> Self {
+        let (command_sender, command_receiver) = mpsc::channel();
+        let (frame_sender, frame_receiver) = mpsc::sync_channel(1);
+
+        let runner = Runner::new(
+            sim,
+            controls,
+            mode,
+            analysis,
+            command_receiver,
+            frame_sender,
+        );
+        thread::Builder::new()
+            .name("simulation".to_string())
+            .spawn(move || runner.run())
+            .expect("Failed to spawn simulation thread");
+
+        Self {
+            commands: command_sender,
+            frames: frame_receiver,
+        }
+    }
+
+    pub fn send(&self, command: Command) {
+        // the thread only ends after the worker is dropped
+        let _ = self.commands.send(command);
+    }
+
+    /// The newest frame published since the last call, if any.
+    pub fn try_frame(&self) -> Option<Frame> {
+        self.frames.try_recv().ok()
+    }
+}
+
+impl Runner {
+    fn new(
+        sim: sim::Simulation,
+        controls: gui::Controls,
+        mode: vis::Mode,
+        analysis: Analysis,
+        commands: mpsc::Receiver<Command>,
+        frames: mpsc::SyncSender<Frame>,
+    ) -> Self {
+        Self {
+            sim,
+            controls,
+            pacer: pacing::Pacer::default(),
+            last_frame: Instant::now(),
+            steps: 0,
+            paused: false,
+            mode,
+            flux: false,
+            changed: true,
+            analysis,
+            commands,
+            frames,
+        }
+    }
+
+    fn run(mut self) {
+        loop {
+            let mut commands: Vec<Command> = self.commands.try_iter().collect();
+            // sleep until told otherwise while there is nothing to simulate
+            if commands.is_empty() && self.paused && !self.changed {
+                match self.commands.recv() {
+                    Ok(command) => commands.push(command),
+                    Err(_) => return,
+                }
+            }
+            for command in commands {
+                self.apply(command);
+            }
+
+            if !self.paused {
+                let now = Instant::now();
+                let elapsed = (now - self.last_frame).as_secs_f64();
+                self.last_frame = now;
+                let steps = self
+                    .pacer
+                    .steps(&self.controls.pacing, elapsed, self.controls.dt);
+                if steps > 0 {
+                    self.sim.multi_step(steps, self.controls.dt);
+                    self.steps = steps;
+                    self.changed = true;
+                } else if !self.changed {
+                    // slow realtime playback, wait for the next step to be due
+                    thread::sleep(Duration::from_millis(1));
+                    continue;
+                }
+            }
+
+            if self.changed {
+                log::debug!(
+                    "sim time: {:.4e} | energy: {:.4e}",
+                    self.sim.time(),
+                    self.sim.energy()
+                );
+                if self.frames.send(self.frame()).is_err() {
+                    return;
+                }
+                self.changed = false;
+            }
+        }
+    }
+
+    fn apply(&mut self, command: Command) {
+        match command {
+            Command::Controls(controls) => {
+                self.controls = controls;
+                self.sim.set_parameters(controls.params);
+            }
+            Command::Pause(paused) => {
+                self.paused = paused;
+                // the pause is not owed to the clock
+                self.pacer.reset();
+                self.last_frame = Instant::now();
+            }
+            Command::Step => {
+                self.sim.step(self.controls.dt);
+                self.steps = 1;
+                log::info!("sim time: {:.4e}", self.sim.time());
+            }
+            Command::Reset => self.sim.reset(),
+            Command::Reverse => {
+                self.sim.reverse(self.controls.dt);
+                log::info!("reversed: {}", self.sim.is_reversed());
+            }
+            Command::Show(mode) => self.mode = mode,
+            Command::ShowFlux(flux) => self.flux = flux,
+            Command::AddProbe(position) => self.sim.add_probe(position),
+            Command::Export => self.export(),
+        }
+        self.changed = true;
+    }
+
+    fn frame(&self) -> Frame {
+        let data = match self.mode {
+            vis::Mode::AveragedIntensity => self.sim.statistics().intensity().clone(),
+            vis::Mode::Rms => self.sim.statistics().rms(),
+            vis::Mode::Envelope => self.sim.statistics().envelope().clone(),
+            vis::Mode::PeakTime => self.sim.statistics().peak_time().clone(),
+            vis::Mode::Phase => self.sim.statistics().phase(),
+            vis::Mode::HarmonicAmplitude => self.sim.statistics().harmonic_amplitude(),
+            _ => self.sim.field().clone(),
+        };
+        Frame {
+            data,
+            mode: self.mode,
+            steps: self.steps,
+            time: self.sim.time(),
+            energy: self.sim.energy(),
+            reversed: self.sim.is_reversed(),
+            courant: self.sim.courant(self.controls.dt),
+            probes: self
+                .sim
+                .probes()
+                .iter()
+                .map(|probe| probe.position)
+                .collect(),
+            profile: self.profile(),
+            flux: self.flux.then(|| {
+                flux::FluxField::new(
+                    &self.sim.flux(self.controls.dt),
+                    self.sim.discretization(),
+                    flux::FluxField::GRID,
+                )
+            }),
+            monitors: self.sim.flux_reports(),
+        }
+    }
+
+    fn profile(&self) -> Option<screen::Profile> {
+        let params = self.controls.params;
+        let wavelength = 2.0 * std::f64::consts::PI * params.c / params.omega;
+        self.sim.detector().map(|detector| {
+            detector.profile(
+                self.analysis
+                    .slits
+                    .as_ref()
+                    .map(|slits| (slits, wavelength)),
+            )
+        })
+    }
+
+    fn export(&self) {
+        let probes = self.sim.probes();
+        let spectra: Vec<_> = probes
+            .iter()
+            .map(|probe| spectrum::Spectrum::of_samples(&probe.samples, &self.analysis.spectrum))
+            .collect();
+        for (i, spectrum) in spectra.iter().enumerate() {
+            if let Some(f) = spectrum.as_ref().and_then(|s| s.dominant_frequency()) {
+                log::info!(
+                    "probe {}: dominant frequency {:.4e} Hz ({:.4e} rad/s)",
+                    i + 1,
+                    f,
+                    2.0 * std::f64::consts::PI * f
+                );
+            }
+        }
+
+        let prefix = format!("wave-simmers-{:.3}s", self.sim.time());
+        let save = |path: String, write: &dyn Fn(std::fs::File) -> std::io::Result<()>| {
+            match std::fs::File::create(&path).and_then(write) {
+                Ok(()) => log::info!("saved {}", path),
+                Err(e) => log::error!("Failed to save {}: {}", path, e),
+            }
+        };
+        save(format!("{}.csv", prefix), &|file| {
+            probe::write_csv(probes, std::io::BufWriter::new(file))
+        });
+        save(format!("{}-spectrum.csv", prefix), &|file| {
+            spectrum::write_csv(&spectra, std::io::BufWriter::new(file))
+        });
+        save(format!("{}-statistics.csv", prefix), &|file| {
+            self.sim.statistics().write_csv(
+                self.sim.size(),
+                self.sim.discretization(),
+                std::io::BufWriter::new(file),
+            )
+        });
+        let reports = self.sim.flux_reports();
+        if !reports.is_empty() {
+            save(format!("{}-flux.csv", prefix), &|file| {
+                flux::write_csv(&reports, std::io::BufWriter::new(file))
+            });
+        }
+        if let Some(profile) = self.profile() {
+            save(format!("{}-screen.csv", prefix), &|file| {
+                profile.write_csv(std::io::BufWriter::new(file))
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn test_worker() {
+        let args = crate::Args::parse_from(["wave-simmers", "--discretization", "11"]);
+        let sim = sim::Simulation::new(&args);
+        let controls = args.controls(&sim);
+        let worker = Worker::spawn(sim, controls, vis::Mode::Field, args.analysis());
+        let next = || worker.frames.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        worker.send(Command::Pause(true));
+        // wait for the frames simulated before the pause was seen
+        let mut frame = next();
+        while let Ok(newer) = worker.frames.recv_timeout(Duration::from_millis(100)) {
+            frame = newer;
+        }
+
+        worker.send(Command::Step);
+        let stepped = next();
+        assert!((stepped.time - frame.time - args.dt).abs() < 1e-12);
+
+        worker.send(Command::Show(vis::Mode::Phase));
+        assert_eq!(next().mode, vis::Mode::Phase);
+
+        worker.send(Command::ShowFlux(true));
+        assert_eq!(next().flux.unwrap().grid, 11);
+
+        worker.send(Command::Reset);
+        let reset = next();
+        assert_eq!(reset.time, 0.0);
+        assert_eq!(reset.data.len(), 11 * 11);
+    }
+
+    #[test]
+    fn test_run_to_frame() {
+        let args = crate::Args::parse_from(["wave-simmers", "--discretization", "11"]);
+        let sim = sim::Simulation::new(&args);
+        let controls = args.controls(&sim);
+        let frame = run_to_frame(sim, controls, vis::Mode::Field, args.analysis(), 10);
+        assert_eq!(frame.steps, 10);
+        assert!((frame.time - 10.0 * args.dt).abs() < 1e-12);
+        assert_eq!(frame.data.len(), 11 * 11);
+    }
+}